@@ -0,0 +1,427 @@
+use std::io::{self, Read, Write};
+
+use cartridge::Cartridge;
+use pia::Pia6532;
+use state;
+use tia::{self, Beam, Tia1A};
+
+// the 6507 only has 13 address lines
+const ADDRESS_MASK: u16     = 0x1FFF;
+
+// chip selects on the 2600 address bus
+const CARTRIDGE_SELECT: u16 = 0x1000; // A12
+const PIA_IO_SELECT: u16    = 0x0200; // A9
+const PIA_SELECT: u16       = 0x0080; // A7
+
+const COLOR_CLOCKS_PER_CYCLE: u16 = 3;
+
+// what the chips decode within their selects
+const TIA_READ_MASK: u16    = 0x0F; // A0-A3
+const TIA_WRITE_MASK: u16   = 0x3F; // A0-A5
+const PIA_RAM_MASK: u16     = 0x7F; // A0-A6
+const PIA_READ_MASK: u16    = 0x07; // A0-A2
+const PIA_WRITE_MASK: u16   = 0x1F; // A0-A4
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Chip {
+    Cartridge,
+    Tia,
+    PiaRam,
+    PiaIo,
+}
+
+// Which chip an address selects; the address has already been masked
+// down to the 6507's 13 lines
+fn select(address: u16) -> Chip {
+    if (address & CARTRIDGE_SELECT) != 0 {
+        Chip::Cartridge
+    } else if (address & PIA_SELECT) == 0 {
+        Chip::Tia
+    } else if (address & PIA_IO_SELECT) == 0 {
+        Chip::PiaRam
+    } else {
+        Chip::PiaIo
+    }
+}
+
+// The lowest of the mirrors that reach the same register or byte as
+// address does, for a read or a write
+pub fn canonical_address(address: u16, write: bool) -> u16 {
+    let address = address & ADDRESS_MASK;
+
+    match select(address) {
+        Chip::Cartridge => address,
+        Chip::Tia       => address & if write { TIA_WRITE_MASK } else { TIA_READ_MASK },
+        Chip::PiaRam    => PIA_SELECT | (address & PIA_RAM_MASK),
+        Chip::PiaIo     => PIA_IO_SELECT | PIA_SELECT | (address & if write { PIA_WRITE_MASK } else { PIA_READ_MASK }),
+    }
+}
+
+// Anything the CPU can read from and write to over its address bus
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, data: u8);
+
+    // A read the CPU only makes because every cycle is a bus access, and
+    // whose value it ignores. It has the same side effects as any other
+    // read; buses that watch accesses can leave it out
+    fn dummy_read(&mut self, address: u16) -> u8 { self.read(address) }
+
+    // Reads without any of the side effects a real read might have, for
+    // debuggers and tracing. Buses that can't do that read as 0
+    fn peek(&self, _address: u16) -> u8 { 0 }
+
+    // Called as the CPU finishes cycles, so the rest of the
+    // machine can keep time with it
+    fn tick(&mut self, _cycles: u8) {}
+
+    // The level of the RDY line; pulling it low (false) halts the CPU on
+    // its next read cycle until it's released
+    fn rdy(&self) -> bool { true }
+
+    // Where the TIA's beam is, for tracing; buses without one stay at 0
+    fn beam(&self) -> Beam { Beam::default() }
+
+    // The level of the IRQ line, which is held low (true) until the
+    // device that raised it is acknowledged
+    #[cfg(feature = "interrupts")]
+    fn irq(&self) -> bool { false }
+
+    // The level of the NMI line; only a change from high to low (false
+    // to true) triggers an interrupt
+    #[cfg(feature = "interrupts")]
+    fn nmi(&self) -> bool { false }
+}
+
+// A flat 64 KiB of RAM, useful for running the CPU on its own
+pub struct FlatRam {
+    memory: Vec<u8>,
+}
+
+impl Default for FlatRam {
+    fn default() -> FlatRam {
+        FlatRam::new()
+    }
+}
+
+impl FlatRam {
+    pub fn new() -> FlatRam {
+        FlatRam {
+            memory: vec![0; 0x10000],
+        }
+    }
+
+    // Copies data into memory starting at address, wrapping at the top
+    pub fn load(&mut self, address: u16, data: &[u8]) {
+        for (offset, byte) in data.iter().enumerate() {
+            let target = address.wrapping_add(offset as u16);
+            self.memory[target as usize] = *byte;
+        }
+    }
+}
+
+impl Bus for FlatRam {
+    fn read(&mut self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.memory[address as usize] = data;
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.memory[address as usize]
+    }
+}
+
+// The 2600's own bus, shared by the PIA, TIA and cartridge
+#[derive(Clone)]
+pub struct AtariBus {
+    pia: Pia6532,
+    tia: Tia1A,
+    cartridge: Cartridge,
+    // the last value on the data bus, which undriven bits float to
+    data_bus: u8,
+    // accesses since the last tick, which the TIA has already been run
+    // through so each one lands on its own color clock
+    accesses: u8,
+}
+
+impl AtariBus {
+    pub fn new(cartridge: Cartridge) -> AtariBus {
+        AtariBus {
+            pia: Pia6532::new(),
+            tia: Tia1A::new(),
+            cartridge,
+            data_bus: 0,
+            accesses: 0,
+        }
+    }
+
+    pub fn pia(&mut self) -> &mut Pia6532 {
+        &mut self.pia
+    }
+
+    pub fn tia(&mut self) -> &mut Tia1A {
+        &mut self.tia
+    }
+
+    pub fn cartridge(&mut self) -> &mut Cartridge {
+        &mut self.cartridge
+    }
+
+    // Runs the TIA and PIA to the end of the cycle an access is made in.
+    // The CPU makes one access a cycle and ticks the bus once an
+    // instruction is done, so writes would otherwise all land after it
+    // and reads would see the timer where the last instruction left it
+    fn catch_up(&mut self) {
+        self.accesses = self.accesses.saturating_add(1);
+        self.tia.tick(COLOR_CLOCKS_PER_CYCLE);
+        self.pia.tick(1);
+    }
+
+    // Writes without running the TIA and PIA through a cycle, for
+    // debuggers changing memory while the machine is stopped. The write
+    // still has its side effects, such as starting the timer
+    pub fn poke(&mut self, address: u16, data: u8) {
+        let address = address & ADDRESS_MASK;
+
+        match select(address) {
+            // the cartridge is ROM
+            Chip::Cartridge => (),
+            Chip::Tia       => self.tia.write(address, data),
+            Chip::PiaRam    => self.pia.write_ram(address, data),
+            Chip::PiaIo     => self.pia.write(address, data),
+        }
+    }
+
+    pub fn save_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        self.pia.save_state(out)?;
+        self.tia.save_state(out)?;
+        self.cartridge.save_state(out)?;
+        state::write_u8(out, self.data_bus)
+    }
+
+    pub fn load_state<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        self.pia.load_state(input)?;
+        self.tia.load_state(input)?;
+        self.cartridge.load_state(input)?;
+        self.data_bus = state::read_u8(input)?;
+        Ok(())
+    }
+}
+
+impl Bus for AtariBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let address = address & ADDRESS_MASK;
+        self.catch_up();
+
+        self.data_bus = match select(address) {
+            Chip::Cartridge => self.cartridge.read(address),
+            Chip::Tia       => {
+                let driven = tia::driven_bits(address);
+                (self.tia.read(address) & driven) | (self.data_bus & !driven)
+            },
+            Chip::PiaRam    => self.pia.read_ram(address),
+            Chip::PiaIo     => self.pia.read(address),
+        };
+        self.data_bus
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.catch_up();
+        self.data_bus = data;
+        self.poke(address, data);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        let address = address & ADDRESS_MASK;
+
+        match select(address) {
+            Chip::Cartridge => self.cartridge.read(address),
+            Chip::Tia       => {
+                let driven = tia::driven_bits(address);
+                (self.tia.peek(address) & driven) | (self.data_bus & !driven)
+            },
+            Chip::PiaRam    => self.pia.read_ram(address),
+            Chip::PiaIo     => self.pia.peek(address),
+        }
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        // both have already run through the cycles with accesses, and
+        // the TIA runs at three color clocks per CPU cycle
+        let remaining = cycles - self.accesses.min(cycles);
+        self.accesses = 0;
+        self.pia.tick(remaining);
+        self.tia.tick(remaining as u16 * COLOR_CLOCKS_PER_CYCLE);
+    }
+
+    // the TIA pulls RDY low after a write to WSYNC
+    fn rdy(&self) -> bool {
+        self.tia.rdy()
+    }
+
+    fn beam(&self) -> Beam {
+        self.tia.beam()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_ram() {
+        let mut ram = FlatRam::new();
+
+        ram.write(0x1234, 0x56);
+
+        assert_eq!(ram.read(0x1234), 0x56);
+        assert_eq!(ram.read(0x1235), 0);
+    }
+
+    #[test]
+    fn flat_ram_load() {
+        let mut ram = FlatRam::new();
+
+        ram.load(0xFFFF, &[0x01, 0x02]);
+
+        assert_eq!(ram.read(0xFFFF), 0x01);
+        assert_eq!(ram.read(0x0000), 0x02);
+    }
+
+    fn atari_bus() -> AtariBus {
+        let mut rom = vec![0; 0x1000];
+        rom[0x0080] = 0xC3;
+        rom[0x0FFC] = 0x00;
+        rom[0x0FFD] = 0xF0;
+
+        AtariBus::new(Cartridge::new(rom).unwrap())
+    }
+
+    #[test]
+    fn atari_bus_ram_mirrors() {
+        let mut bus = atari_bus();
+
+        bus.write(0x0080, 0x42);
+
+        assert_eq!(bus.read(0x0180), 0x42);
+        assert_eq!(bus.read(0x0480), 0x42);
+        assert_eq!(bus.read(0x2080), 0x42);
+
+        bus.write(0x01FF, 0x24);
+
+        // the stack in page one is the same RAM as zero page
+        assert_eq!(bus.read(0x00FF), 0x24);
+    }
+
+    #[test]
+    fn atari_bus_cartridge() {
+        let mut bus = atari_bus();
+
+        assert_eq!(bus.read(0x1080), 0xC3);
+        assert_eq!(bus.read(0xF080), 0xC3);
+        assert_eq!(bus.read(0xFFFD), 0xF0);
+        assert_eq!(bus.read(0x1FFD), 0xF0);
+    }
+
+    #[test]
+    fn atari_bus_pia_io() {
+        let mut bus = atari_bus();
+
+        // SWCHA reads the joystick pins, not RAM
+        assert_eq!(bus.read(0x0280), 0xFF);
+
+        bus.write(0x0296, 10); // TIM64T
+        bus.tick(1);
+
+        assert_eq!(bus.read(0x0284), 9); // INTIM
+        assert_eq!(bus.read(0x0384), 9);
+        assert_eq!(bus.read(0x0080), 0);
+    }
+
+    #[test]
+    fn atari_bus_tia() {
+        let mut bus = atari_bus();
+
+        bus.write(0x0040, 0x42);
+        bus.write(0x0100, 0x24);
+
+        // TIA writes never land in RAM or the PIA
+        assert_eq!(bus.read(0x0080), 0);
+        assert_eq!(bus.read(0x0180), 0);
+    }
+
+    #[test]
+    fn atari_bus_peek() {
+        let mut bus = atari_bus();
+
+        bus.write(0x0294, 0); // TIM1T
+        bus.tick(2);
+
+        // no acknowledged interrupt
+        assert_eq!(bus.peek(0x0285), 0x80);
+        assert_eq!(bus.peek(0x0285), 0x80);
+        assert_eq!(bus.peek(0x1080), 0xC3);
+    }
+
+    #[test]
+    fn atari_bus_pia_catches_up() {
+        let mut bus = atari_bus();
+
+        bus.write(0x0294, 10); // TIM1T
+
+        // each access runs the timer on, before the bus is ticked
+        assert_eq!(bus.read(0x0284), 9);
+        assert_eq!(bus.read(0x0284), 8);
+
+        // the three cycles with accesses aren't run again
+        bus.tick(5);
+
+        assert_eq!(bus.read(0x0284), 5);
+    }
+
+    #[test]
+    fn atari_bus_poke() {
+        let mut bus = atari_bus();
+
+        bus.poke(0x0294, 10); // TIM1T
+        bus.poke(0x0080, 0x42);
+
+        // neither write ran the timer or the beam on
+        assert_eq!(bus.peek(0x0284), 10);
+        assert_eq!(bus.peek(0x0080), 0x42);
+        assert_eq!(bus.beam(), Beam::default());
+    }
+
+    #[test]
+    fn atari_bus_tia_reads_float() {
+        let mut bus = atari_bus();
+        bus.tia().set_fire_button(1, false);
+
+        bus.write(0x0080, 0x7F);
+
+        // INPT5 drives D7 high and the rest is left over from the write
+        assert_eq!(bus.peek(0x000D), 0xFF);
+        assert_eq!(bus.read(0x003D), 0xFF);
+
+        // the collision latches drive D6 as well, CXBLPF doesn't
+        assert_eq!(bus.peek(0x0000), 0x3F);
+        assert_eq!(bus.peek(0x0006), 0x7F);
+
+        bus.read(0x1080);
+
+        assert_eq!(bus.read(0x000D), 0xC3);
+    }
+
+    #[test]
+    fn canonical_addresses() {
+        assert_eq!(canonical_address(0x0180, false), 0x0080);
+        assert_eq!(canonical_address(0x2080, true), 0x0080);
+        assert_eq!(canonical_address(0x0140, true), 0x0000);
+        assert_eq!(canonical_address(0x003D, false), 0x000D);
+        assert_eq!(canonical_address(0x03FC, false), 0x0284);
+        assert_eq!(canonical_address(0xF123, false), 0x1123);
+    }
+}
@@ -0,0 +1,91 @@
+use std::io::{self, Read, Write};
+
+use state;
+
+// the cartridge slot sees A0-A11, so each bank is 4K
+pub const BANK_SIZE: usize  = 0x1000;
+
+// The default is an empty slot, which reads as 0
+#[derive(Clone, Default)]
+pub struct Cartridge {
+    rom: Vec<u8>,
+}
+
+impl Cartridge {
+    // Fails on ROMs over 4K, which need bank switching to be run right
+    pub fn new(rom: Vec<u8>) -> io::Result<Cartridge> {
+        if rom.len() > BANK_SIZE {
+            let message = format!("{} byte ROM needs bank switching, which isn't supported", rom.len());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+
+        Ok(Cartridge {
+            rom,
+        })
+    }
+
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    // Writes a checksum of the ROM, so a state can't be loaded into the
+    // wrong game, then the selected bank and whether there's extra RAM.
+    // A 4K cartridge is always on bank 0 with no RAM
+    pub fn save_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        state::write_u32(out, state::checksum(&self.rom))?;
+        state::write_u8(out, 0)?;
+        state::write_bool(out, false)
+    }
+
+    pub fn load_state<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        if state::read_u32(input)? != state::checksum(&self.rom) {
+            return Err(state::invalid("saved with a different cartridge"));
+        }
+        if state::read_u8(input)? != 0 {
+            return Err(state::invalid("bank out of range"));
+        }
+        if state::read_bool(input)? {
+            return Err(state::invalid("extra RAM doesn't match the cartridge"));
+        }
+        Ok(())
+    }
+
+    // Reads from the 4K cartridge window; only A0-A11 are significant
+    pub fn read(&self, address: u16) -> u8 {
+        if self.rom.is_empty() {
+            return 0;
+        }
+
+        // carts smaller than 4K are mirrored across the window
+        let address = (address & 0x0FFF) as usize;
+        self.rom[address % self.rom.len()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrors_2k() {
+        let mut rom = vec![0; 0x800];
+        rom[0x10] = 0x42;
+        let cartridge = Cartridge::new(rom).unwrap();
+
+        assert_eq!(cartridge.read(0x0010), 0x42);
+        assert_eq!(cartridge.read(0x0810), 0x42);
+    }
+
+    #[test]
+    fn empty() {
+        let cartridge = Cartridge::default();
+
+        assert_eq!(cartridge.read(0x0000), 0);
+    }
+
+    #[test]
+    fn rejects_bank_switched() {
+        assert!(Cartridge::new(vec![0; 0x1000]).is_ok());
+        assert!(Cartridge::new(vec![0; 0x2000]).is_err());
+    }
+}
@@ -1,6 +1,11 @@
-use super::Cartridge;
-use pia::Pia6532;
-use tia::Tia1A;
+use std::cell::RefCell;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::rc::Rc;
+
+use bus::Bus;
+use state;
+use trace::Tracer;
 
 // for flags register
 const CARRY_MASK: u8                = 0x01;
@@ -8,22 +13,44 @@ const ZERO_RESULT_MASK: u8          = 0x02;
 const INTERRUPT_DISABLE_MASK: u8    = 0x04;
 const DECIMAL_MASK: u8              = 0x08;
 const BREAK_COMMAND_MASK: u8        = 0x10;
+const UNUSED_MASK: u8               = 0x20;
 const OVERFLOW_MASK: u8             = 0x40;
 const NEGATIVE_MASK: u8             = 0x80;
 
-// to extract lower byte from word
-const LOW_BYTE_MASK: u16            = 0xFF;
+// the stack lives in page one
+const STACK_BASE: u16               = 0x0100;
+
+// RESET and BRK/IRQ vectors; with only 13 address lines on the 6507
+// these land in the last bytes of the cartridge
+const RESET_VECTOR: u16             = 0xFFFC;
+const IRQ_VECTOR: u16               = 0xFFFE;
+#[cfg(feature = "interrupts")]
+const NMI_VECTOR: u16               = 0xFFFA;
+
+// cycles taken by the reset sequence
+const RESET_CYCLES: u8              = 7;
+#[cfg(feature = "interrupts")]
+const INTERRUPT_CYCLES: u8          = 7;
 
 // to extract nibbles for BCD operations
 const LOW_NIBBLE_MASK: u8           = 0x0F;
 
-enum AddressMode {
+// ANE/LXA OR the accumulator with a chip-dependent value before masking
+const DEFAULT_MAGIC_CONSTANT: u8    = 0xEE;
+
+// no instruction makes more bus accesses than this
+const MAX_INSTRUCTION_CYCLES: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressMode {
     Immediate,
     ZeroPage,
     ZeroPageX,
+    ZeroPageY,
     Absolute,
     AbsoluteX,
     AbsoluteY,
+    Indirect,
     IndirectX,
     IndirectY,
     Relative,
@@ -31,6 +58,411 @@ enum AddressMode {
     None
 }
 
+// What an instruction does once its operand has been fetched. Most fit
+// one of the first five shapes, the rest drive the bus themselves
+#[derive(Clone, Copy)]
+enum Handler {
+    Read(fn(&mut Mos6507, u8)),
+    Store(fn(&mut Mos6507) -> u8),
+    Modify(fn(&mut Mos6507, u8) -> u8),
+    Implied(fn(&mut Mos6507)),
+    Branch(fn(&mut Mos6507, u8) -> u8),
+    UnstableStore(fn(&mut Mos6507) -> u8),
+    Brk,
+    Jmp,
+    Jsr,
+    Kil,
+    Pha,
+    Php,
+    Pla,
+    Plp,
+    Rti,
+    Rts,
+}
+
+// Everything the decoder, cycle counter and disassembler need to know
+// about an opcode
+#[derive(Clone, Copy)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub address_mode: AddressMode,
+    pub bytes: u8,
+    // before page crossing and branch penalties
+    pub cycles: u8,
+    handler: Handler,
+}
+
+impl Opcode {
+    const fn new(mnemonic: &'static str, address_mode: AddressMode, cycles: u8, handler: Handler) -> Opcode {
+        let bytes = match address_mode {
+            AddressMode::Absolute   |
+            AddressMode::AbsoluteX  |
+            AddressMode::AbsoluteY  |
+            AddressMode::Indirect       => 3,
+            AddressMode::Accumulator |
+            AddressMode::None           => 1,
+            _                           => 2,
+        };
+
+        Opcode {
+            mnemonic,
+            address_mode,
+            bytes,
+            cycles,
+            handler,
+        }
+    }
+
+    // Indexed reads take an extra cycle when the index carries into the
+    // high byte; stores and read-modify-write forms always pay it
+    pub fn has_page_penalty(&self) -> bool {
+        match self.handler {
+            Handler::Read(_) => matches!(self.address_mode,
+                                         AddressMode::AbsoluteX |
+                                         AddressMode::AbsoluteY |
+                                         AddressMode::IndirectY),
+            _                => false,
+        }
+    }
+}
+
+pub static OPCODES: [Opcode; 256] = [
+    /* 0x00 */ Opcode::new("BRK", AddressMode::None,        7, Handler::Brk),
+    /* 0x01 */ Opcode::new("ORA", AddressMode::IndirectX,   6, Handler::Read(Mos6507::ora)),
+    /* 0x02 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x03 */ Opcode::new("SLO", AddressMode::IndirectX,   8, Handler::Modify(Mos6507::slo)),
+    /* 0x04 */ Opcode::new("NOP", AddressMode::ZeroPage,    3, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x05 */ Opcode::new("ORA", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::ora)),
+    /* 0x06 */ Opcode::new("ASL", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::asl)),
+    /* 0x07 */ Opcode::new("SLO", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::slo)),
+    /* 0x08 */ Opcode::new("PHP", AddressMode::None,        3, Handler::Php),
+    /* 0x09 */ Opcode::new("ORA", AddressMode::Immediate,   2, Handler::Read(Mos6507::ora)),
+    /* 0x0A */ Opcode::new("ASL", AddressMode::Accumulator, 2, Handler::Modify(Mos6507::asl)),
+    /* 0x0B */ Opcode::new("ANC", AddressMode::Immediate,   2, Handler::Read(Mos6507::anc)),
+    /* 0x0C */ Opcode::new("NOP", AddressMode::Absolute,    4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x0D */ Opcode::new("ORA", AddressMode::Absolute,    4, Handler::Read(Mos6507::ora)),
+    /* 0x0E */ Opcode::new("ASL", AddressMode::Absolute,    6, Handler::Modify(Mos6507::asl)),
+    /* 0x0F */ Opcode::new("SLO", AddressMode::Absolute,    6, Handler::Modify(Mos6507::slo)),
+    /* 0x10 */ Opcode::new("BPL", AddressMode::Relative,    2, Handler::Branch(Mos6507::bpl)),
+    /* 0x11 */ Opcode::new("ORA", AddressMode::IndirectY,   5, Handler::Read(Mos6507::ora)),
+    /* 0x12 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x13 */ Opcode::new("SLO", AddressMode::IndirectY,   8, Handler::Modify(Mos6507::slo)),
+    /* 0x14 */ Opcode::new("NOP", AddressMode::ZeroPageX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x15 */ Opcode::new("ORA", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::ora)),
+    /* 0x16 */ Opcode::new("ASL", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::asl)),
+    /* 0x17 */ Opcode::new("SLO", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::slo)),
+    /* 0x18 */ Opcode::new("CLC", AddressMode::None,        2, Handler::Implied(Mos6507::clc)),
+    /* 0x19 */ Opcode::new("ORA", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::ora)),
+    /* 0x1A */ Opcode::new("NOP", AddressMode::None,        2, Handler::Implied(Mos6507::nop)),
+    /* 0x1B */ Opcode::new("SLO", AddressMode::AbsoluteY,   7, Handler::Modify(Mos6507::slo)),
+    /* 0x1C */ Opcode::new("NOP", AddressMode::AbsoluteX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x1D */ Opcode::new("ORA", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::ora)),
+    /* 0x1E */ Opcode::new("ASL", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::asl)),
+    /* 0x1F */ Opcode::new("SLO", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::slo)),
+    /* 0x20 */ Opcode::new("JSR", AddressMode::Absolute,    6, Handler::Jsr),
+    /* 0x21 */ Opcode::new("AND", AddressMode::IndirectX,   6, Handler::Read(Mos6507::and)),
+    /* 0x22 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x23 */ Opcode::new("RLA", AddressMode::IndirectX,   8, Handler::Modify(Mos6507::rla)),
+    /* 0x24 */ Opcode::new("BIT", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::bit)),
+    /* 0x25 */ Opcode::new("AND", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::and)),
+    /* 0x26 */ Opcode::new("ROL", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::rol)),
+    /* 0x27 */ Opcode::new("RLA", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::rla)),
+    /* 0x28 */ Opcode::new("PLP", AddressMode::None,        4, Handler::Plp),
+    /* 0x29 */ Opcode::new("AND", AddressMode::Immediate,   2, Handler::Read(Mos6507::and)),
+    /* 0x2A */ Opcode::new("ROL", AddressMode::Accumulator, 2, Handler::Modify(Mos6507::rol)),
+    /* 0x2B */ Opcode::new("ANC", AddressMode::Immediate,   2, Handler::Read(Mos6507::anc)),
+    /* 0x2C */ Opcode::new("BIT", AddressMode::Absolute,    4, Handler::Read(Mos6507::bit)),
+    /* 0x2D */ Opcode::new("AND", AddressMode::Absolute,    4, Handler::Read(Mos6507::and)),
+    /* 0x2E */ Opcode::new("ROL", AddressMode::Absolute,    6, Handler::Modify(Mos6507::rol)),
+    /* 0x2F */ Opcode::new("RLA", AddressMode::Absolute,    6, Handler::Modify(Mos6507::rla)),
+    /* 0x30 */ Opcode::new("BMI", AddressMode::Relative,    2, Handler::Branch(Mos6507::bmi)),
+    /* 0x31 */ Opcode::new("AND", AddressMode::IndirectY,   5, Handler::Read(Mos6507::and)),
+    /* 0x32 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x33 */ Opcode::new("RLA", AddressMode::IndirectY,   8, Handler::Modify(Mos6507::rla)),
+    /* 0x34 */ Opcode::new("NOP", AddressMode::ZeroPageX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x35 */ Opcode::new("AND", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::and)),
+    /* 0x36 */ Opcode::new("ROL", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::rol)),
+    /* 0x37 */ Opcode::new("RLA", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::rla)),
+    /* 0x38 */ Opcode::new("SEC", AddressMode::None,        2, Handler::Implied(Mos6507::sec)),
+    /* 0x39 */ Opcode::new("AND", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::and)),
+    /* 0x3A */ Opcode::new("NOP", AddressMode::None,        2, Handler::Implied(Mos6507::nop)),
+    /* 0x3B */ Opcode::new("RLA", AddressMode::AbsoluteY,   7, Handler::Modify(Mos6507::rla)),
+    /* 0x3C */ Opcode::new("NOP", AddressMode::AbsoluteX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x3D */ Opcode::new("AND", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::and)),
+    /* 0x3E */ Opcode::new("ROL", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::rol)),
+    /* 0x3F */ Opcode::new("RLA", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::rla)),
+    /* 0x40 */ Opcode::new("RTI", AddressMode::None,        6, Handler::Rti),
+    /* 0x41 */ Opcode::new("EOR", AddressMode::IndirectX,   6, Handler::Read(Mos6507::eor)),
+    /* 0x42 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x43 */ Opcode::new("SRE", AddressMode::IndirectX,   8, Handler::Modify(Mos6507::sre)),
+    /* 0x44 */ Opcode::new("NOP", AddressMode::ZeroPage,    3, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x45 */ Opcode::new("EOR", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::eor)),
+    /* 0x46 */ Opcode::new("LSR", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::lsr)),
+    /* 0x47 */ Opcode::new("SRE", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::sre)),
+    /* 0x48 */ Opcode::new("PHA", AddressMode::None,        3, Handler::Pha),
+    /* 0x49 */ Opcode::new("EOR", AddressMode::Immediate,   2, Handler::Read(Mos6507::eor)),
+    /* 0x4A */ Opcode::new("LSR", AddressMode::Accumulator, 2, Handler::Modify(Mos6507::lsr)),
+    /* 0x4B */ Opcode::new("ALR", AddressMode::Immediate,   2, Handler::Read(Mos6507::alr)),
+    /* 0x4C */ Opcode::new("JMP", AddressMode::Absolute,    3, Handler::Jmp),
+    /* 0x4D */ Opcode::new("EOR", AddressMode::Absolute,    4, Handler::Read(Mos6507::eor)),
+    /* 0x4E */ Opcode::new("LSR", AddressMode::Absolute,    6, Handler::Modify(Mos6507::lsr)),
+    /* 0x4F */ Opcode::new("SRE", AddressMode::Absolute,    6, Handler::Modify(Mos6507::sre)),
+    /* 0x50 */ Opcode::new("BVC", AddressMode::Relative,    2, Handler::Branch(Mos6507::bvc)),
+    /* 0x51 */ Opcode::new("EOR", AddressMode::IndirectY,   5, Handler::Read(Mos6507::eor)),
+    /* 0x52 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x53 */ Opcode::new("SRE", AddressMode::IndirectY,   8, Handler::Modify(Mos6507::sre)),
+    /* 0x54 */ Opcode::new("NOP", AddressMode::ZeroPageX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x55 */ Opcode::new("EOR", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::eor)),
+    /* 0x56 */ Opcode::new("LSR", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::lsr)),
+    /* 0x57 */ Opcode::new("SRE", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::sre)),
+    /* 0x58 */ Opcode::new("CLI", AddressMode::None,        2, Handler::Implied(Mos6507::cli)),
+    /* 0x59 */ Opcode::new("EOR", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::eor)),
+    /* 0x5A */ Opcode::new("NOP", AddressMode::None,        2, Handler::Implied(Mos6507::nop)),
+    /* 0x5B */ Opcode::new("SRE", AddressMode::AbsoluteY,   7, Handler::Modify(Mos6507::sre)),
+    /* 0x5C */ Opcode::new("NOP", AddressMode::AbsoluteX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x5D */ Opcode::new("EOR", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::eor)),
+    /* 0x5E */ Opcode::new("LSR", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::lsr)),
+    /* 0x5F */ Opcode::new("SRE", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::sre)),
+    /* 0x60 */ Opcode::new("RTS", AddressMode::None,        6, Handler::Rts),
+    /* 0x61 */ Opcode::new("ADC", AddressMode::IndirectX,   6, Handler::Read(Mos6507::adc)),
+    /* 0x62 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x63 */ Opcode::new("RRA", AddressMode::IndirectX,   8, Handler::Modify(Mos6507::rra)),
+    /* 0x64 */ Opcode::new("NOP", AddressMode::ZeroPage,    3, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x65 */ Opcode::new("ADC", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::adc)),
+    /* 0x66 */ Opcode::new("ROR", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::ror)),
+    /* 0x67 */ Opcode::new("RRA", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::rra)),
+    /* 0x68 */ Opcode::new("PLA", AddressMode::None,        4, Handler::Pla),
+    /* 0x69 */ Opcode::new("ADC", AddressMode::Immediate,   2, Handler::Read(Mos6507::adc)),
+    /* 0x6A */ Opcode::new("ROR", AddressMode::Accumulator, 2, Handler::Modify(Mos6507::ror)),
+    /* 0x6B */ Opcode::new("ARR", AddressMode::Immediate,   2, Handler::Read(Mos6507::arr)),
+    /* 0x6C */ Opcode::new("JMP", AddressMode::Indirect,    5, Handler::Jmp),
+    /* 0x6D */ Opcode::new("ADC", AddressMode::Absolute,    4, Handler::Read(Mos6507::adc)),
+    /* 0x6E */ Opcode::new("ROR", AddressMode::Absolute,    6, Handler::Modify(Mos6507::ror)),
+    /* 0x6F */ Opcode::new("RRA", AddressMode::Absolute,    6, Handler::Modify(Mos6507::rra)),
+    /* 0x70 */ Opcode::new("BVS", AddressMode::Relative,    2, Handler::Branch(Mos6507::bvs)),
+    /* 0x71 */ Opcode::new("ADC", AddressMode::IndirectY,   5, Handler::Read(Mos6507::adc)),
+    /* 0x72 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x73 */ Opcode::new("RRA", AddressMode::IndirectY,   8, Handler::Modify(Mos6507::rra)),
+    /* 0x74 */ Opcode::new("NOP", AddressMode::ZeroPageX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x75 */ Opcode::new("ADC", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::adc)),
+    /* 0x76 */ Opcode::new("ROR", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::ror)),
+    /* 0x77 */ Opcode::new("RRA", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::rra)),
+    /* 0x78 */ Opcode::new("SEI", AddressMode::None,        2, Handler::Implied(Mos6507::sei)),
+    /* 0x79 */ Opcode::new("ADC", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::adc)),
+    /* 0x7A */ Opcode::new("NOP", AddressMode::None,        2, Handler::Implied(Mos6507::nop)),
+    /* 0x7B */ Opcode::new("RRA", AddressMode::AbsoluteY,   7, Handler::Modify(Mos6507::rra)),
+    /* 0x7C */ Opcode::new("NOP", AddressMode::AbsoluteX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x7D */ Opcode::new("ADC", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::adc)),
+    /* 0x7E */ Opcode::new("ROR", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::ror)),
+    /* 0x7F */ Opcode::new("RRA", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::rra)),
+    /* 0x80 */ Opcode::new("NOP", AddressMode::Immediate,   2, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x81 */ Opcode::new("STA", AddressMode::IndirectX,   6, Handler::Store(|cpu| cpu.a)),
+    /* 0x82 */ Opcode::new("NOP", AddressMode::Immediate,   2, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x83 */ Opcode::new("SAX", AddressMode::IndirectX,   6, Handler::Store(|cpu| cpu.a & cpu.x)),
+    /* 0x84 */ Opcode::new("STY", AddressMode::ZeroPage,    3, Handler::Store(|cpu| cpu.y)),
+    /* 0x85 */ Opcode::new("STA", AddressMode::ZeroPage,    3, Handler::Store(|cpu| cpu.a)),
+    /* 0x86 */ Opcode::new("STX", AddressMode::ZeroPage,    3, Handler::Store(|cpu| cpu.x)),
+    /* 0x87 */ Opcode::new("SAX", AddressMode::ZeroPage,    3, Handler::Store(|cpu| cpu.a & cpu.x)),
+    /* 0x88 */ Opcode::new("DEY", AddressMode::None,        2, Handler::Implied(Mos6507::dey)),
+    /* 0x89 */ Opcode::new("NOP", AddressMode::Immediate,   2, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0x8A */ Opcode::new("TXA", AddressMode::None,        2, Handler::Implied(Mos6507::txa)),
+    /* 0x8B */ Opcode::new("ANE", AddressMode::Immediate,   2, Handler::Read(Mos6507::ane)),
+    /* 0x8C */ Opcode::new("STY", AddressMode::Absolute,    4, Handler::Store(|cpu| cpu.y)),
+    /* 0x8D */ Opcode::new("STA", AddressMode::Absolute,    4, Handler::Store(|cpu| cpu.a)),
+    /* 0x8E */ Opcode::new("STX", AddressMode::Absolute,    4, Handler::Store(|cpu| cpu.x)),
+    /* 0x8F */ Opcode::new("SAX", AddressMode::Absolute,    4, Handler::Store(|cpu| cpu.a & cpu.x)),
+    /* 0x90 */ Opcode::new("BCC", AddressMode::Relative,    2, Handler::Branch(Mos6507::bcc)),
+    /* 0x91 */ Opcode::new("STA", AddressMode::IndirectY,   6, Handler::Store(|cpu| cpu.a)),
+    /* 0x92 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0x93 */ Opcode::new("SHA", AddressMode::IndirectY,   6, Handler::UnstableStore(|cpu| cpu.a & cpu.x)),
+    /* 0x94 */ Opcode::new("STY", AddressMode::ZeroPageX,   4, Handler::Store(|cpu| cpu.y)),
+    /* 0x95 */ Opcode::new("STA", AddressMode::ZeroPageX,   4, Handler::Store(|cpu| cpu.a)),
+    /* 0x96 */ Opcode::new("STX", AddressMode::ZeroPageY,   4, Handler::Store(|cpu| cpu.x)),
+    /* 0x97 */ Opcode::new("SAX", AddressMode::ZeroPageY,   4, Handler::Store(|cpu| cpu.a & cpu.x)),
+    /* 0x98 */ Opcode::new("TYA", AddressMode::None,        2, Handler::Implied(Mos6507::tya)),
+    /* 0x99 */ Opcode::new("STA", AddressMode::AbsoluteY,   5, Handler::Store(|cpu| cpu.a)),
+    /* 0x9A */ Opcode::new("TXS", AddressMode::None,        2, Handler::Implied(Mos6507::txs)),
+    /* 0x9B */ Opcode::new("TAS", AddressMode::AbsoluteY,   5, Handler::UnstableStore(Mos6507::tas)),
+    /* 0x9C */ Opcode::new("SHY", AddressMode::AbsoluteX,   5, Handler::UnstableStore(|cpu| cpu.y)),
+    /* 0x9D */ Opcode::new("STA", AddressMode::AbsoluteX,   5, Handler::Store(|cpu| cpu.a)),
+    /* 0x9E */ Opcode::new("SHX", AddressMode::AbsoluteY,   5, Handler::UnstableStore(|cpu| cpu.x)),
+    /* 0x9F */ Opcode::new("SHA", AddressMode::AbsoluteY,   5, Handler::UnstableStore(|cpu| cpu.a & cpu.x)),
+    /* 0xA0 */ Opcode::new("LDY", AddressMode::Immediate,   2, Handler::Read(Mos6507::ldy)),
+    /* 0xA1 */ Opcode::new("LDA", AddressMode::IndirectX,   6, Handler::Read(Mos6507::lda)),
+    /* 0xA2 */ Opcode::new("LDX", AddressMode::Immediate,   2, Handler::Read(Mos6507::ldx)),
+    /* 0xA3 */ Opcode::new("LAX", AddressMode::IndirectX,   6, Handler::Read(Mos6507::lax)),
+    /* 0xA4 */ Opcode::new("LDY", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::ldy)),
+    /* 0xA5 */ Opcode::new("LDA", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::lda)),
+    /* 0xA6 */ Opcode::new("LDX", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::ldx)),
+    /* 0xA7 */ Opcode::new("LAX", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::lax)),
+    /* 0xA8 */ Opcode::new("TAY", AddressMode::None,        2, Handler::Implied(Mos6507::tay)),
+    /* 0xA9 */ Opcode::new("LDA", AddressMode::Immediate,   2, Handler::Read(Mos6507::lda)),
+    /* 0xAA */ Opcode::new("TAX", AddressMode::None,        2, Handler::Implied(Mos6507::tax)),
+    /* 0xAB */ Opcode::new("LXA", AddressMode::Immediate,   2, Handler::Read(Mos6507::lxa)),
+    /* 0xAC */ Opcode::new("LDY", AddressMode::Absolute,    4, Handler::Read(Mos6507::ldy)),
+    /* 0xAD */ Opcode::new("LDA", AddressMode::Absolute,    4, Handler::Read(Mos6507::lda)),
+    /* 0xAE */ Opcode::new("LDX", AddressMode::Absolute,    4, Handler::Read(Mos6507::ldx)),
+    /* 0xAF */ Opcode::new("LAX", AddressMode::Absolute,    4, Handler::Read(Mos6507::lax)),
+    /* 0xB0 */ Opcode::new("BCS", AddressMode::Relative,    2, Handler::Branch(Mos6507::bcs)),
+    /* 0xB1 */ Opcode::new("LDA", AddressMode::IndirectY,   5, Handler::Read(Mos6507::lda)),
+    /* 0xB2 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0xB3 */ Opcode::new("LAX", AddressMode::IndirectY,   5, Handler::Read(Mos6507::lax)),
+    /* 0xB4 */ Opcode::new("LDY", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::ldy)),
+    /* 0xB5 */ Opcode::new("LDA", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::lda)),
+    /* 0xB6 */ Opcode::new("LDX", AddressMode::ZeroPageY,   4, Handler::Read(Mos6507::ldx)),
+    /* 0xB7 */ Opcode::new("LAX", AddressMode::ZeroPageY,   4, Handler::Read(Mos6507::lax)),
+    /* 0xB8 */ Opcode::new("CLV", AddressMode::None,        2, Handler::Implied(Mos6507::clv)),
+    /* 0xB9 */ Opcode::new("LDA", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::lda)),
+    /* 0xBA */ Opcode::new("TSX", AddressMode::None,        2, Handler::Implied(Mos6507::tsx)),
+    /* 0xBB */ Opcode::new("LAS", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::las)),
+    /* 0xBC */ Opcode::new("LDY", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::ldy)),
+    /* 0xBD */ Opcode::new("LDA", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::lda)),
+    /* 0xBE */ Opcode::new("LDX", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::ldx)),
+    /* 0xBF */ Opcode::new("LAX", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::lax)),
+    /* 0xC0 */ Opcode::new("CPY", AddressMode::Immediate,   2, Handler::Read(Mos6507::cpy)),
+    /* 0xC1 */ Opcode::new("CMP", AddressMode::IndirectX,   6, Handler::Read(Mos6507::cmp)),
+    /* 0xC2 */ Opcode::new("NOP", AddressMode::Immediate,   2, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0xC3 */ Opcode::new("DCP", AddressMode::IndirectX,   8, Handler::Modify(Mos6507::dcp)),
+    /* 0xC4 */ Opcode::new("CPY", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::cpy)),
+    /* 0xC5 */ Opcode::new("CMP", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::cmp)),
+    /* 0xC6 */ Opcode::new("DEC", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::dec)),
+    /* 0xC7 */ Opcode::new("DCP", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::dcp)),
+    /* 0xC8 */ Opcode::new("INY", AddressMode::None,        2, Handler::Implied(Mos6507::iny)),
+    /* 0xC9 */ Opcode::new("CMP", AddressMode::Immediate,   2, Handler::Read(Mos6507::cmp)),
+    /* 0xCA */ Opcode::new("DEX", AddressMode::None,        2, Handler::Implied(Mos6507::dex)),
+    /* 0xCB */ Opcode::new("SBX", AddressMode::Immediate,   2, Handler::Read(Mos6507::sbx)),
+    /* 0xCC */ Opcode::new("CPY", AddressMode::Absolute,    4, Handler::Read(Mos6507::cpy)),
+    /* 0xCD */ Opcode::new("CMP", AddressMode::Absolute,    4, Handler::Read(Mos6507::cmp)),
+    /* 0xCE */ Opcode::new("DEC", AddressMode::Absolute,    6, Handler::Modify(Mos6507::dec)),
+    /* 0xCF */ Opcode::new("DCP", AddressMode::Absolute,    6, Handler::Modify(Mos6507::dcp)),
+    /* 0xD0 */ Opcode::new("BNE", AddressMode::Relative,    2, Handler::Branch(Mos6507::bne)),
+    /* 0xD1 */ Opcode::new("CMP", AddressMode::IndirectY,   5, Handler::Read(Mos6507::cmp)),
+    /* 0xD2 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0xD3 */ Opcode::new("DCP", AddressMode::IndirectY,   8, Handler::Modify(Mos6507::dcp)),
+    /* 0xD4 */ Opcode::new("NOP", AddressMode::ZeroPageX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0xD5 */ Opcode::new("CMP", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::cmp)),
+    /* 0xD6 */ Opcode::new("DEC", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::dec)),
+    /* 0xD7 */ Opcode::new("DCP", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::dcp)),
+    /* 0xD8 */ Opcode::new("CLD", AddressMode::None,        2, Handler::Implied(Mos6507::cld)),
+    /* 0xD9 */ Opcode::new("CMP", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::cmp)),
+    /* 0xDA */ Opcode::new("NOP", AddressMode::None,        2, Handler::Implied(Mos6507::nop)),
+    /* 0xDB */ Opcode::new("DCP", AddressMode::AbsoluteY,   7, Handler::Modify(Mos6507::dcp)),
+    /* 0xDC */ Opcode::new("NOP", AddressMode::AbsoluteX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0xDD */ Opcode::new("CMP", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::cmp)),
+    /* 0xDE */ Opcode::new("DEC", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::dec)),
+    /* 0xDF */ Opcode::new("DCP", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::dcp)),
+    /* 0xE0 */ Opcode::new("CPX", AddressMode::Immediate,   2, Handler::Read(Mos6507::cpx)),
+    /* 0xE1 */ Opcode::new("SBC", AddressMode::IndirectX,   6, Handler::Read(Mos6507::sbc)),
+    /* 0xE2 */ Opcode::new("NOP", AddressMode::Immediate,   2, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0xE3 */ Opcode::new("ISB", AddressMode::IndirectX,   8, Handler::Modify(Mos6507::isb)),
+    /* 0xE4 */ Opcode::new("CPX", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::cpx)),
+    /* 0xE5 */ Opcode::new("SBC", AddressMode::ZeroPage,    3, Handler::Read(Mos6507::sbc)),
+    /* 0xE6 */ Opcode::new("INC", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::inc)),
+    /* 0xE7 */ Opcode::new("ISB", AddressMode::ZeroPage,    5, Handler::Modify(Mos6507::isb)),
+    /* 0xE8 */ Opcode::new("INX", AddressMode::None,        2, Handler::Implied(Mos6507::inx)),
+    /* 0xE9 */ Opcode::new("SBC", AddressMode::Immediate,   2, Handler::Read(Mos6507::sbc)),
+    /* 0xEA */ Opcode::new("NOP", AddressMode::None,        2, Handler::Implied(Mos6507::nop)),
+    /* 0xEB */ Opcode::new("SBC", AddressMode::Immediate,   2, Handler::Read(Mos6507::sbc)),
+    /* 0xEC */ Opcode::new("CPX", AddressMode::Absolute,    4, Handler::Read(Mos6507::cpx)),
+    /* 0xED */ Opcode::new("SBC", AddressMode::Absolute,    4, Handler::Read(Mos6507::sbc)),
+    /* 0xEE */ Opcode::new("INC", AddressMode::Absolute,    6, Handler::Modify(Mos6507::inc)),
+    /* 0xEF */ Opcode::new("ISB", AddressMode::Absolute,    6, Handler::Modify(Mos6507::isb)),
+    /* 0xF0 */ Opcode::new("BEQ", AddressMode::Relative,    2, Handler::Branch(Mos6507::beq)),
+    /* 0xF1 */ Opcode::new("SBC", AddressMode::IndirectY,   5, Handler::Read(Mos6507::sbc)),
+    /* 0xF2 */ Opcode::new("KIL", AddressMode::None,        2, Handler::Kil),
+    /* 0xF3 */ Opcode::new("ISB", AddressMode::IndirectY,   8, Handler::Modify(Mos6507::isb)),
+    /* 0xF4 */ Opcode::new("NOP", AddressMode::ZeroPageX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0xF5 */ Opcode::new("SBC", AddressMode::ZeroPageX,   4, Handler::Read(Mos6507::sbc)),
+    /* 0xF6 */ Opcode::new("INC", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::inc)),
+    /* 0xF7 */ Opcode::new("ISB", AddressMode::ZeroPageX,   6, Handler::Modify(Mos6507::isb)),
+    /* 0xF8 */ Opcode::new("SED", AddressMode::None,        2, Handler::Implied(Mos6507::sed)),
+    /* 0xF9 */ Opcode::new("SBC", AddressMode::AbsoluteY,   4, Handler::Read(Mos6507::sbc)),
+    /* 0xFA */ Opcode::new("NOP", AddressMode::None,        2, Handler::Implied(Mos6507::nop)),
+    /* 0xFB */ Opcode::new("ISB", AddressMode::AbsoluteY,   7, Handler::Modify(Mos6507::isb)),
+    /* 0xFC */ Opcode::new("NOP", AddressMode::AbsoluteX,   4, Handler::Read(|cpu, _| cpu.nop())),
+    /* 0xFD */ Opcode::new("SBC", AddressMode::AbsoluteX,   4, Handler::Read(Mos6507::sbc)),
+    /* 0xFE */ Opcode::new("INC", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::inc)),
+    /* 0xFF */ Opcode::new("ISB", AddressMode::AbsoluteX,   7, Handler::Modify(Mos6507::isb)),
+];
+
+// Stands in for the real bus while an instruction is re-run during
+// cycle stepping: accesses already made are answered from the log, the
+// one for the current cycle goes through to the bus, and any after it
+// are dropped
+struct CycleBus<'a, B: 'a + Bus> {
+    bus: &'a mut B,
+    log: &'a mut Vec<u8>,
+    cycle: usize,
+    accesses: usize,
+    stalled: bool,
+}
+
+impl<'a, B: Bus> CycleBus<'a, B> {
+    fn access<F: FnOnce(&mut B) -> u8>(&mut self, access: F) -> u8 {
+        let access_cycle = self.accesses;
+        self.accesses += 1;
+
+        if access_cycle < self.cycle {
+            self.log[access_cycle]
+        } else if access_cycle == self.cycle {
+            let data = access(self.bus);
+            self.log.push(data);
+            data
+        } else {
+            0
+        }
+    }
+
+    fn read_with<F: FnOnce(&mut B) -> u8>(&mut self, read: F) -> u8 {
+        // with RDY low the read still goes out, but the cycle repeats
+        if self.accesses == self.cycle && !self.bus.rdy() {
+            self.stalled = true;
+            read(self.bus);
+        } else if !self.stalled {
+            return self.access(read);
+        }
+
+        self.accesses += 1;
+        0
+    }
+}
+
+impl<'a, B: Bus> Bus for CycleBus<'a, B> {
+    fn read(&mut self, address: u16) -> u8 {
+        self.read_with(|bus| bus.read(address))
+    }
+
+    fn dummy_read(&mut self, address: u16) -> u8 {
+        self.read_with(|bus| bus.dummy_read(address))
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        if self.stalled {
+            self.accesses += 1;
+            return;
+        }
+
+        self.access(|bus| {
+            bus.write(address, data);
+            data
+        });
+    }
+
+    // the real bus is ticked once per cycle instead, and RDY is
+    // handled per read above
+
+    #[cfg(feature = "interrupts")]
+    fn irq(&self) -> bool {
+        self.bus.irq()
+    }
+
+    #[cfg(feature = "interrupts")]
+    fn nmi(&self) -> bool {
+        self.bus.nmi()
+    }
+}
+
+#[derive(Clone)]
 pub struct Mos6507 {
     a: u8,
     x: u8,
@@ -38,6 +470,25 @@ pub struct Mos6507 {
     sp: u8,
     pc: u16,
     flags: u8,
+    jammed: bool,
+    magic_constant: u8,
+    cycles: u64,
+    // what the bus returned for each cycle of a partly stepped instruction
+    cycle_log: Vec<u8>,
+    // shared, since the CPU is cloned for every cycle it ticks
+    tracer: Option<Rc<RefCell<Tracer>>>,
+    #[cfg(feature = "interrupts")]
+    irq_inhibit: bool,
+    #[cfg(feature = "interrupts")]
+    nmi_line: bool,
+    #[cfg(feature = "interrupts")]
+    nmi_pending: bool,
+}
+
+impl Default for Mos6507 {
+    fn default() -> Mos6507 {
+        Mos6507::new()
+    }
 }
 
 impl Mos6507 {
@@ -49,126 +500,428 @@ impl Mos6507 {
             sp: 0u8,
             pc: 0u16,
             flags: 0u8,
+            jammed: false,
+            magic_constant: DEFAULT_MAGIC_CONSTANT,
+            cycles: 0,
+            cycle_log: Vec::new(),
+            tracer: None,
+            #[cfg(feature = "interrupts")]
+            irq_inhibit: false,
+            #[cfg(feature = "interrupts")]
+            nmi_line: false,
+            #[cfg(feature = "interrupts")]
+            nmi_pending: false,
+        }
+    }
+
+    // Total CPU cycles executed since power on
+    pub fn cycles(&self) -> u64 {
+        self.cycles + self.cycle_log.len() as u64
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    // The status register as PHP would push it, minus the B flag
+    pub fn flags(&self) -> u8 {
+        self.flags | UNUSED_MASK
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value;
+    }
+
+    pub fn set_a(&mut self, value: u8) {
+        self.a = value;
+    }
+
+    pub fn set_x(&mut self, value: u8) {
+        self.x = value;
+    }
+
+    pub fn set_y(&mut self, value: u8) {
+        self.y = value;
+    }
+
+    pub fn set_sp(&mut self, value: u8) {
+        self.sp = value;
+    }
+
+    // B and the unused bit aren't really flags, so they're dropped as PLP does
+    pub fn set_flags(&mut self, value: u8) {
+        self.flags = value & !(BREAK_COMMAND_MASK | UNUSED_MASK);
+    }
+
+    // Logs each instruction before it executes, or stops logging with None
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer.map(|tracer| Rc::new(RefCell::new(tracer)));
+    }
+
+    // Sets the value that the unstable ANE/LXA opcodes OR into the
+    // accumulator, which varies between individual chips
+    pub fn set_magic_constant(&mut self, value: u8) {
+        self.magic_constant = value;
+    }
+
+    // A KIL/JAM opcode locks the CPU until it is reset
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
+    // Writes the registers, cycle count and any partly ticked instruction.
+    // The interrupt lines are always written so states work either way
+    // the crate is built
+    pub fn save_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for register in &[self.a, self.x, self.y, self.sp, self.flags, self.magic_constant] {
+            state::write_u8(out, *register)?;
+        }
+        state::write_u16(out, self.pc)?;
+        state::write_bool(out, self.jammed)?;
+        state::write_u64(out, self.cycles)?;
+
+        state::write_u8(out, self.cycle_log.len() as u8)?;
+        out.write_all(&self.cycle_log)?;
+
+        #[cfg(feature = "interrupts")]
+        let lines = [self.irq_inhibit, self.nmi_line, self.nmi_pending];
+        #[cfg(not(feature = "interrupts"))]
+        let lines = [false; 3];
+
+        for line in &lines {
+            state::write_bool(out, *line)?;
+        }
+        Ok(())
+    }
+
+    pub fn load_state<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        self.a = state::read_u8(input)?;
+        self.x = state::read_u8(input)?;
+        self.y = state::read_u8(input)?;
+        self.sp = state::read_u8(input)?;
+        self.flags = state::read_u8(input)?;
+        self.magic_constant = state::read_u8(input)?;
+        self.pc = state::read_u16(input)?;
+        self.jammed = state::read_bool(input)?;
+        self.cycles = state::read_u64(input)?;
+
+        let logged = state::read_u8(input)? as usize;
+        if logged >= MAX_INSTRUCTION_CYCLES {
+            return Err(state::invalid("too many cycles into an instruction"));
+        }
+        self.cycle_log = vec![0; logged];
+        input.read_exact(&mut self.cycle_log)?;
+
+        let mut lines = [false; 3];
+        for line in lines.iter_mut() {
+            *line = state::read_bool(input)?;
         }
+
+        #[cfg(feature = "interrupts")]
+        {
+            self.irq_inhibit = lines[0];
+            self.nmi_line = lines[1];
+            self.nmi_pending = lines[2];
+        }
+        Ok(())
     }
 
-    pub fn run(&mut self, pia: &Pia6532, tia: &Tia1A, rom: &Cartridge) {
-        // begin execution from the RESET vector in rom 0xFFFC-0xFFFB
-        self.pc = self.read_word(pia, tia, rom, 0xFFFB);
+    pub fn run<B: Bus>(&mut self, bus: &mut B) {
+        self.reset(bus);
 
         loop {
-           self.execute_instruction(pia, tia, rom);
-           // TODO - handle interupts
+           self.step(bus);
         }
     }
 
-    fn execute_instruction(&mut self, pia: &Pia6532, tia: &Tia1A, rom: &Cartridge) {
-        let opcode = self.read_byte(pia, tia, rom, &AddressMode::Absolute);
-        let address_mode = self.get_address_mode(opcode); 
-        let operand = self.read_byte(pia, tia, rom, &address_mode);
+    // Runs the 7 cycle RESET sequence, which begins execution from the
+    // RESET vector at 0xFFFC-0xFFFD
+    pub fn reset<B: Bus>(&mut self, bus: &mut B) {
+        // two dummy reads of the next instruction
+        self.dummy_read(bus, self.pc);
+        self.dummy_read(bus, self.pc);
+
+        // then what would be an interrupt's three pushes, with the
+        // writes suppressed, which is why SP ends up at 0xFD
+        for _ in 0..3 {
+            self.dummy_read(bus, STACK_BASE | self.sp as u16);
+            self.sp = self.sp.wrapping_sub(1);
+        }
 
-        match opcode {
-            // ADC
-            0x69 | 0x65 | 0x75 | 0x6D |
-            0x7D | 0x79 | 0x61 | 0x71    => {
-                self.adc(operand);
-            },
-            // AND
-            0x29 | 0x25 | 0x35 | 0x2D |
-            0x3D | 0x39 | 0x21 | 0x31   => {
-                self.and(operand);
-            },
-            // ASL
-            0x0A | 0x06 | 0x16 | 0x0E |
-            0x1E                        => {
-                self.asl(operand, &address_mode);
-            },
-            // BCC
-            0x90                        => {
-                self.bcc(operand);
-            },
-            // BCS
-            0xB0                        => {
-                self.bcs(operand);
-            },
-            // BEQ
-            0xF0                        => {
-                self.beq(operand);
-            },
-            // BIT
-            0x24 | 0x2C                 => {
-                self.bit(operand);
+        self.set_flag(true, INTERRUPT_DISABLE_MASK);
+        self.jammed = false;
+        self.pc = self.read_word(bus, RESET_VECTOR);
+
+        // abandon any instruction that was part way through
+        self.cycles += self.cycle_log.len() as u64;
+        self.cycle_log.clear();
+
+        #[cfg(feature = "interrupts")]
+        {
+            self.irq_inhibit = true;
+            self.nmi_pending = false;
+        }
+
+        self.cycles += RESET_CYCLES as u64;
+        bus.tick(RESET_CYCLES);
+    }
+
+    // Executes a single instruction and returns the number of cycles it took,
+    // or finishes the one in progress if it has been partly stepped by tick
+    pub fn step<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        if !self.cycle_log.is_empty() {
+            let mut cycles = 1;
+            while !self.tick(bus) {
+                cycles += 1;
+            }
+            return cycles;
+        }
+
+        // RDY low holds the CPU on its next read, repeating it each cycle.
+        // Instructions end with a write at most, so when stepping by
+        // instruction the stall lands on the opcode fetch
+        let mut stalled_cycles = 0;
+        while !self.jammed && !bus.rdy() {
+            self.dummy_read(bus, self.pc);
+            self.cycles += 1;
+            bus.tick(1);
+            stalled_cycles += 1;
+        }
+
+        self.trace(bus);
+        stalled_cycles + self.execute_instruction(bus)
+    }
+
+    // Advances exactly one cycle, making that cycle's bus access and
+    // ticking the bus once. Returns true when an instruction finishes.
+    //
+    // The instruction is re-run from the start each cycle against a
+    // CycleBus, so only the access for this cycle reaches the bus and
+    // stepping by cycle or by instruction behaves identically. Registers
+    // don't change until the instruction's last cycle
+    pub fn tick<B: Bus>(&mut self, bus: &mut B) -> bool {
+        let mut log = mem::take(&mut self.cycle_log);
+        let cycle = log.len();
+        let mut cpu = self.clone();
+
+        let (accesses, stalled) = {
+            let mut cycle_bus = CycleBus {
+                bus,
+                log: &mut log,
+                cycle,
+                accesses: 0,
+                stalled: false,
+            };
+
+            cpu.execute_instruction(&mut cycle_bus);
+            (cycle_bus.accesses, cycle_bus.stalled)
+        };
+
+        // trace once the opcode fetch has gone through
+        if cycle == 0 && !stalled {
+            self.trace(bus);
+        }
+
+        bus.tick(1);
+
+        // a stalled cycle counts, but the instruction hasn't moved on
+        if stalled {
+            self.cycles += 1;
+            self.cycle_log = log;
+            return false;
+        }
+
+        // a jammed CPU has nothing to re-run, so every cycle finishes
+        if accesses <= cycle + 1 {
+            log.clear();
+            cpu.cycle_log = log;
+            *self = cpu;
+            true
+        } else {
+            self.cycle_log = log;
+            false
+        }
+    }
+
+    // Executes a single instruction and returns the number of cycles it took
+    fn execute_instruction<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        if self.jammed {
+            // a jammed CPU keeps the clock running but never fetches again
+            self.cycles += 1;
+            bus.tick(1);
+            return 1;
+        }
+
+        #[cfg(feature = "interrupts")]
+        {
+            if self.service_interrupt(bus) {
+                return INTERRUPT_CYCLES;
+            }
+        }
+
+        let opcode = self.read_byte_by_addr(bus, self.pc);
+        let entry = &OPCODES[opcode as usize];
+        let address_mode = entry.address_mode;
+        let next = self.pc.wrapping_add(1);
+
+        let mut cycles = entry.cycles;
+        let mut advance_pc = true;
+        #[cfg(feature = "interrupts")]
+        let flags_before = self.flags;
+
+        // every cycle is a bus access, so instructions that don't need
+        // one read the byte after the opcode and throw it away
+        match entry.handler {
+            Handler::Read(handler)          => {
+                let (operand, page_crossed) = self.read_operand(bus, &address_mode);
+                if page_crossed {
+                    cycles += 1;
+                }
+                handler(self, operand);
             },
-            // BMI
-            0x30                        => {
-                self.bmi(operand);
+            Handler::Store(handler)         => {
+                let (address, _) = self.effective_address(bus, &address_mode, true);
+                let value = handler(self);
+                self.write_byte_by_addr(bus, address, value);
             },
-            // BNE
-            0xD0                        => {
-                self.bmi(operand);
+            Handler::Modify(handler)        => {
+                if let AddressMode::Accumulator = address_mode {
+                    self.dummy_read(bus, next);
+                    let operand = self.a;
+                    self.a = handler(self, operand);
+                } else {
+                    let (address, _) = self.effective_address(bus, &address_mode, true);
+                    let operand = self.read_byte_by_addr(bus, address);
+
+                    // the NMOS part writes the unmodified value back
+                    // while it works out the result
+                    self.write_byte_by_addr(bus, address, operand);
+                    let result = handler(self, operand);
+                    self.write_byte_by_addr(bus, address, result);
+                }
             },
-            // BPL
-            0x10                        => {
-                self.bpl(operand);
+            Handler::Implied(handler)       => {
+                self.dummy_read(bus, next);
+                handler(self);
             },
-            // BRK - TODO
-            // BVC
-            0x50                        => {
-                self.bvc(operand);
+            Handler::Branch(handler)        => {
+                let operand = self.read_byte_by_addr(bus, next);
+                let extra_cycles = handler(self, operand);
+
+                // a taken branch fetches from the next instruction, then
+                // from the target without the carry into the high byte
+                let fallthrough = next.wrapping_add(1);
+                let target = self.pc.wrapping_add(2);
+
+                if extra_cycles > 0 {
+                    self.dummy_read(bus, fallthrough);
+                }
+                if extra_cycles > 1 {
+                    self.dummy_read(bus, (fallthrough & 0xFF00) | (target & 0x00FF));
+                }
+
+                cycles += extra_cycles;
             },
-            // BVS
-            0x70                        => {
-                self.bvs(operand);
+            Handler::UnstableStore(handler) => {
+                let (address, _) = self.effective_address(bus, &address_mode, true);
+                let value = handler(self);
+                self.unstable_store(bus, &address_mode, address, value);
             },
-            // CLC
-            0x18                        => {
-                self.clc();
+            Handler::Brk                    => {
+                // the padding byte
+                self.dummy_read(bus, next);
+                self.brk(bus);
+                advance_pc = false;
             },
-            // CLD
-            0xD8                        => {
-                self.cld();
+            Handler::Jmp                    => {
+                self.jmp(bus, &address_mode);
+                advance_pc = false;
             },
-            // CLI
-            0x58                        => {
-                self.cli();
+            Handler::Jsr                    => {
+                self.jsr(bus);
+                advance_pc = false;
             },
-            // CLV
-            0xB8                        => {
-                self.clv();
+            Handler::Kil                    => {
+                self.dummy_read(bus, next);
+                self.kil();
+                advance_pc = false;
             },
-            // CMP
-            0xC9 | 0xC5 | 0xD5 | 0xCD |   
-            0xDD | 0xD9 | 0xC1 | 0xD1   => {
-                self.cmp(operand);
+            Handler::Pha                    => {
+                self.dummy_read(bus, next);
+                self.pha(bus);
             },
-            // CPX
-            0xE0 | 0xE4 | 0xEC          => {
-                self.cpx(operand);
+            Handler::Php                    => {
+                self.dummy_read(bus, next);
+                self.php(bus);
             },
-            // CPY
-            0xC0 | 0xC4 | 0xCC          => {
-                self.cpy(operand);
+            Handler::Pla                    => {
+                self.dummy_read(bus, next);
+                self.read_stack(bus);
+                self.pla(bus);
             },
-            // DEC TODO
-            // DEX
-            0xCA                        => {
-                self.dex();
+            Handler::Plp                    => {
+                self.dummy_read(bus, next);
+                self.read_stack(bus);
+                self.plp(bus);
             },
-            // EOR TODO
-            // INC TODO
-            // INX
-            0xE8                        => {
-                self.inx();
+            Handler::Rti                    => {
+                self.dummy_read(bus, next);
+                self.read_stack(bus);
+                self.rti(bus);
+                advance_pc = false;
             },
-            // INY
-            0xC8                        => {
-                self.iny();
+            Handler::Rts                    => {
+                self.dummy_read(bus, next);
+                self.read_stack(bus);
+                self.rts(bus);
+                advance_pc = false;
             },
+        }
+
+        if advance_pc {
+            self.pc = self.pc.wrapping_add(entry.bytes as u16);
+        }
+
+        #[cfg(feature = "interrupts")]
+        {
+            // CLI, SEI and PLP change I after the CPU has already polled
+            // for IRQs, so the old value holds for one more instruction
+            self.irq_inhibit = match opcode {
+                0x58 | 0x78 | 0x28 => (flags_before & INTERRUPT_DISABLE_MASK) != 0,
+                _                  => self.flag_set(INTERRUPT_DISABLE_MASK),
+            };
+        }
+
+        self.cycles += cycles as u64;
+        bus.tick(cycles);
+        cycles
+    }
 
-            _       => panic!("Unknown opcode {}", opcode),
+    fn trace<B: Bus>(&self, bus: &B) {
+        if self.jammed {
+            return;
         }
 
-        self.pc += self.get_pc_offset(address_mode);
+        if let Some(ref tracer) = self.tracer {
+            // a failed write shouldn't stop the emulation
+            let _ = tracer.borrow_mut().trace(self, bus);
+        }
     }
 
     fn flag_set(&self, mask: u8) -> bool {
@@ -177,277 +930,706 @@ impl Mos6507 {
 
     fn set_flag(&mut self, value: bool, mask: u8) {
         if value {
-            self.flags = self.flags | mask;
+            self.flags |= mask;
         } else { 
-            self.flags = self.flags & !mask;
+            self.flags &= !mask;
         }
+    }
+
+    // CMP, CPX and CPY subtract without borrow and only keep the flags;
+    // carry is set when the register is at least the operand, unsigned
+    fn compare(&mut self, register: u8, operand: u8) {
+        let result = register.wrapping_sub(operand);
+
+        self.set_flag(register >= operand, CARRY_MASK);
+        self.set_zero_and_negative(result);
+    }
+
+    fn set_zero_and_negative(&mut self, value: u8) {
+        self.set_flag(value == 0, ZERO_RESULT_MASK);
+        self.set_flag((value & NEGATIVE_MASK) > 0, NEGATIVE_MASK);
     } 
 
-    fn get_address_mode(&self, opcode: u8) -> AddressMode {
-        match opcode {
-            0x69 | 0x29 | 0xC9 | 0xE0 |
-            0xC0
-                => AddressMode::Immediate,
-            0x65 | 0x25 | 0x06 | 0x24 |
-            0xC5 | 0xE4 | 0xC4
-                => AddressMode::ZeroPage,
-            0x75 | 0x35 | 0x16 | 0xD5
-                => AddressMode::ZeroPageX,
-            0x6D | 0x2D | 0x0E | 0x2C |
-            0xCD | 0xEC | 0xCC
-                => AddressMode::Absolute,
-            0x7D | 0x3D | 0x1E | 0xDD
-                => AddressMode::AbsoluteX,
-            0x79 | 0x39 | 0xD9
-                => AddressMode::AbsoluteY,
-            0x61 | 0x21 | 0xC1
-                => AddressMode::IndirectX,
-            0x71 | 0x31 | 0xD1
-                => AddressMode::IndirectY,
-            0x0A
-                => AddressMode::Accumulator,
-            0x90 | 0xB0 | 0x30 | 0xD0 |
-            0x10 | 0x50
-                => AddressMode::Relative,
-            _   => AddressMode::None,
-        }
-    }
-
-    fn get_pc_offset(&self, address_mode: AddressMode) -> u16 {
-        match address_mode {
-            AddressMode::Absolute   |
-            AddressMode::AbsoluteX  | 
-            AddressMode::AbsoluteY      => 3,
-            AddressMode::None           => 1,
-            _                           => 2,
+    // Resolves the address an instruction operates on from the operand
+    // bytes that follow the opcode, making the same accesses as the chip
+    // does along the way. Indexed modes first read from the address before
+    // the carry into the high byte is fixed, which reads can skip when
+    // there's no carry but writes never do. Returns whether a page was
+    // crossed
+    fn effective_address<B: Bus>(&self, bus: &mut B, address_mode: &AddressMode, writes: bool) -> (u16, bool) {
+        let operand_address = self.pc.wrapping_add(1);
+
+        let (base, index) = match *address_mode {
+            AddressMode::Immediate  |
+            AddressMode::Relative       => return (operand_address, false),
+            AddressMode::ZeroPage       => return (self.read_byte_by_addr(bus, operand_address) as u16, false),
+            AddressMode::ZeroPageX  |
+            AddressMode::ZeroPageY      => {
+                let base = self.read_byte_by_addr(bus, operand_address);
+                self.dummy_read(bus, base as u16);
+
+                // indexing never leaves page zero
+                let index = if let AddressMode::ZeroPageX = *address_mode { self.x } else { self.y };
+                return (base.wrapping_add(index) as u16, false);
+            },
+            AddressMode::Absolute       => return (self.read_word(bus, operand_address), false),
+            AddressMode::AbsoluteX      => (self.read_word(bus, operand_address), self.x),
+            AddressMode::AbsoluteY      => (self.read_word(bus, operand_address), self.y),
+            AddressMode::Indirect       => {
+                // the NMOS part doesn't carry into the pointer's high byte,
+                // so JMP ($xxFF) takes its high byte from $xx00
+                let pointer = self.read_word(bus, operand_address);
+                let high_pointer = (pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF);
+
+                let low_byte = self.read_byte_by_addr(bus, pointer) as u16;
+                let high_byte = self.read_byte_by_addr(bus, high_pointer) as u16;
+                return ((high_byte << 8) + low_byte, false);
+            },
+            AddressMode::IndirectX      => {
+                let pointer = self.read_byte_by_addr(bus, operand_address);
+                self.dummy_read(bus, pointer as u16);
+                return (self.read_zero_page_word(bus, pointer.wrapping_add(self.x)), false);
+            },
+            AddressMode::IndirectY      => {
+                let pointer = self.read_byte_by_addr(bus, operand_address);
+                (self.read_zero_page_word(bus, pointer), self.y)
+            },
+            AddressMode::Accumulator |
+            AddressMode::None           => unreachable!("implied operands have no address"),
+        };
+
+        let address = base.wrapping_add(index as u16);
+        let page_crossed = (base & 0xFF00) != (address & 0xFF00);
+
+        if page_crossed || writes {
+            self.dummy_read(bus, (base & 0xFF00) | (address & 0x00FF));
+        }
+
+        (address, page_crossed)
+    }
+
+    // Fetches the value a read instruction works on, and whether indexing
+    // crossed a page
+    fn read_operand<B: Bus>(&self, bus: &mut B, address_mode: &AddressMode) -> (u8, bool) {
+        match *address_mode {
+            AddressMode::Accumulator |
+            AddressMode::None           => (self.read_byte_by_addr(bus, self.pc.wrapping_add(1)), false),
+            _                           => {
+                let (address, page_crossed) = self.effective_address(bus, address_mode, false);
+                (self.read_byte_by_addr(bus, address), page_crossed)
+            },
         }
     }
 
-    fn read_word(&self, pia: &Pia6532, tia: &Tia1A, rom: &Cartridge, address: u16) -> u16 {
-        let low_byte = self.read_byte_by_addr(pia, tia, rom, address) as u16;
-        let high_byte = self.read_byte_by_addr(pia, tia, rom, address + 1) as u16;
+    fn read_word<B: Bus>(&self, bus: &mut B, address: u16) -> u16 {
+        let low_byte = self.read_byte_by_addr(bus, address) as u16;
+        let high_byte = self.read_byte_by_addr(bus, address.wrapping_add(1)) as u16;
         (high_byte << 8) + low_byte
     }
 
-    fn read_byte(&self, pia: &Pia6532, tia: &Tia1A, rom: &Cartridge, address_mode: &AddressMode) -> u8 {
-        //TODO map address to underlying components
-        0
+    // Pointers in zero page wrap from $FF back to $00
+    fn read_zero_page_word<B: Bus>(&self, bus: &mut B, pointer: u8) -> u16 {
+        let low_byte = self.read_byte_by_addr(bus, pointer as u16) as u16;
+        let high_byte = self.read_byte_by_addr(bus, pointer.wrapping_add(1) as u16) as u16;
+        (high_byte << 8) + low_byte
     }
-    
-    fn read_byte_by_addr(&self, pia: &Pia6532, tia: &Tia1A, rom: &Cartridge, address: u16) -> u8 {
-        //TODO map address to underlying components
-        0
+
+    fn read_byte_by_addr<B: Bus>(&self, bus: &mut B, address: u16) -> u8 {
+        bus.read(address)
     }
     
-    fn write(&self, pia: Pia6532, tia: Tia1A, rom: Cartridge, address: AddressMode, data: u8) {
-        //TODO - map address to underlying components
+    // A read made only because every cycle accesses the bus, whose value
+    // is thrown away
+    fn dummy_read<B: Bus>(&self, bus: &mut B, address: u16) {
+        bus.dummy_read(address);
     }
- 
-    fn branch_on_flag(&mut self, operand: u8, predicate: bool, mask: u8) {
-        // 6507 uses signed operand for branch, hence this mess
-        let signed_operand = operand as i8;
 
-        if self.flag_set(mask) == predicate {
-            if signed_operand < 0 {
-                let temp = -signed_operand;
-                self.pc -= temp as u16;
-            } else {
-                self.pc += operand as u16;
-            }
-        }
+    fn write_byte_by_addr<B: Bus>(&self, bus: &mut B, address: u16, data: u8) {
+        bus.write(address, data);
     }
 
-    fn iny(&mut self) {
-        let result = (self.y as i8) + 1;
+    fn push<B: Bus>(&mut self, bus: &mut B, data: u8) {
+        self.write_byte_by_addr(bus, STACK_BASE | self.sp as u16, data);
+        self.sp = self.sp.wrapping_sub(1);
+    }
 
-        self.set_flag(result == 0, ZERO_RESULT_MASK);
-        self.set_flag(result < 0, NEGATIVE_MASK);
-        self.y = (result as u8);
+    // The cycle spent incrementing SP before a pull still reads the stack
+    fn read_stack<B: Bus>(&self, bus: &mut B) {
+        self.dummy_read(bus, STACK_BASE | self.sp as u16);
     }
 
-    fn inx(&mut self) {
-        let result = (self.x as i8) + 1;
+    fn pull<B: Bus>(&mut self, bus: &mut B) -> u8 {
+        self.sp = self.sp.wrapping_add(1);
+        self.read_byte_by_addr(bus, STACK_BASE | self.sp as u16)
+    }
 
-        self.set_flag(result == 0, ZERO_RESULT_MASK);
-        self.set_flag(result < 0, NEGATIVE_MASK);
-        self.x = (result as u8);
+    fn push_word<B: Bus>(&mut self, bus: &mut B, data: u16) {
+        self.push(bus, (data >> 8) as u8);
+        self.push(bus, data as u8);
     }
 
-    fn dex(&mut self) {
-       let result = (self.x as i8) - 1;
+    fn pull_word<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        let low_byte = self.pull(bus) as u16;
+        let high_byte = self.pull(bus) as u16;
+        (high_byte << 8) + low_byte
+    }
 
-       self.set_flag(result == 0, ZERO_RESULT_MASK);
-       self.set_flag(result < 0, NEGATIVE_MASK);
+    // The sequence shared by BRK, IRQ and NMI: push the return address and
+    // the flags, then jump through a vector. B is only ever set in the
+    // pushed copy of the flags, so a handler can tell BRK from IRQ
+    fn interrupt<B: Bus>(&mut self, bus: &mut B, return_address: u16, break_command: bool) {
+        self.push_word(bus, return_address);
 
-       self.x = (result as u8);
-    }
+        let mut value = self.flags | UNUSED_MASK;
+        if break_command {
+            value |= BREAK_COMMAND_MASK;
+        }
+        self.push(bus, value);
 
-    fn cpy(&mut self, operand: u8) {
-        let result = (self.y as i8) - (operand as i8);
+        self.set_flag(true, INTERRUPT_DISABLE_MASK);
 
-        self.set_flag(result == 0, ZERO_RESULT_MASK);
-        self.set_flag(result < 0, NEGATIVE_MASK);
-        self.set_flag(result >= 0, CARRY_MASK);
+        let vector = self.interrupt_vector(bus);
+        self.pc = self.read_word(bus, vector);
     }
 
-    fn cpx(&mut self, operand: u8) {
-        let result = (self.x as i8) - (operand as i8);
+    #[cfg(not(feature = "interrupts"))]
+    fn interrupt_vector<B: Bus>(&mut self, _bus: &mut B) -> u16 {
+        IRQ_VECTOR
+    }
 
-        self.set_flag(result == 0, ZERO_RESULT_MASK);
-        self.set_flag(result < 0, NEGATIVE_MASK);
-        self.set_flag(result >= 0, CARRY_MASK);
+    // An NMI that arrives before the vector is fetched hijacks a BRK or
+    // IRQ in progress, which then runs the NMI handler instead
+    #[cfg(feature = "interrupts")]
+    fn interrupt_vector<B: Bus>(&mut self, bus: &mut B) -> u16 {
+        self.poll_nmi(bus);
+
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            NMI_VECTOR
+        } else {
+            IRQ_VECTOR
+        }
     }
 
-    fn cmp(&mut self, operand: u8) {
-        let result = (self.a as i8) - (operand as i8);
+    #[cfg(feature = "interrupts")]
+    fn poll_nmi<B: Bus>(&mut self, bus: &mut B) {
+        // NMI is edge triggered, so it's latched until serviced
+        let line = bus.nmi();
+        if line && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = line;
+    }
 
-        self.set_flag(result == 0, ZERO_RESULT_MASK);
-        self.set_flag(result < 0, NEGATIVE_MASK);
-        self.set_flag(result >= 0, CARRY_MASK);
+    // Runs the IRQ or NMI sequence if one is due before the next
+    // instruction, returning whether it did
+    #[cfg(feature = "interrupts")]
+    fn service_interrupt<B: Bus>(&mut self, bus: &mut B) -> bool {
+        self.poll_nmi(bus);
+
+        if !self.nmi_pending && (self.irq_inhibit || !bus.irq()) {
+            return false;
+        }
+
+        // the opcode fetch happens, but is replaced with a BRK and PC
+        // isn't incremented, so the handler returns to the same place
+        self.dummy_read(bus, self.pc);
+        self.dummy_read(bus, self.pc);
+
+        let return_address = self.pc;
+        self.interrupt(bus, return_address, false);
+        self.irq_inhibit = true;
+
+        self.cycles += INTERRUPT_CYCLES as u64;
+        bus.tick(INTERRUPT_CYCLES);
+        true
     }
+ 
+    // Returns the extra cycles spent: one for a taken branch and
+    // another if the target is on a different page
+    fn branch_on_flag(&mut self, operand: u8, predicate: bool, mask: u8) -> u8 {
+        // 6507 uses signed operand for branch, so sign extend it
+        let signed_operand = operand as i8;
 
-    fn clv(&mut self) {
-        self.set_flag(false, OVERFLOW_MASK);
+        if self.flag_set(mask) == predicate {
+            let next = self.pc.wrapping_add(2);
+            self.pc = self.pc.wrapping_add(signed_operand as i16 as u16);
+
+            if (next & 0xFF00) != (self.pc.wrapping_add(2) & 0xFF00) {
+                2
+            } else {
+                1
+            }
+        } else {
+            0
+        }
     }
 
-    fn cli(&mut self) {
-        self.set_flag(false, INTERRUPT_DISABLE_MASK);
+    // SHA/SHX/SHY/TAS store the value ANDed with the high byte of the base
+    // address plus one; when indexing crosses a page the high byte of the
+    // target address is replaced by that same value
+    fn unstable_store<B: Bus>(&mut self, bus: &mut B, address_mode: &AddressMode, address: u16, value: u8) {
+        let index = match *address_mode {
+            AddressMode::AbsoluteX => self.x,
+            _                      => self.y,
+        };
+        let base = address.wrapping_sub(index as u16);
+
+        let result = value & ((base >> 8) as u8).wrapping_add(1);
+        let mut address = address;
+
+        if (address & 0xFF00) != (base & 0xFF00) {
+            address = ((result as u16) << 8) | (address & 0x00FF);
+        }
+
+        self.write_byte_by_addr(bus, address, result);
     }
 
-    fn cld(&mut self) {
-        self.set_flag(false, DECIMAL_MASK);
+    fn tas(&mut self) -> u8 {
+        self.sp = self.a & self.x;
+        self.sp
     }
 
-    fn clc(&mut self) {
-        self.set_flag(false, CARRY_MASK);
+    fn sre(&mut self, operand: u8) -> u8 {
+        let result = self.lsr(operand);
+        self.eor(result);
+        result
     }
 
-    fn bvs(&mut self, operand: u8) {
-        self.branch_on_flag(operand, true, OVERFLOW_MASK);
+    fn slo(&mut self, operand: u8) -> u8 {
+        let result = self.asl(operand);
+        self.ora(result);
+        result
     }
-    
-    fn bvc(&mut self, operand: u8) {
-        self.branch_on_flag(operand, false, OVERFLOW_MASK);
+
+    fn sbx(&mut self, operand: u8) {
+        let value = self.a & self.x;
+
+        self.set_flag(value >= operand, CARRY_MASK);
+        self.x = value.wrapping_sub(operand);
+
+        let temp = self.x;
+        self.set_zero_and_negative(temp);
     }
 
-    fn bpl(&mut self, operand: u8) {
-        self.branch_on_flag(operand, false, NEGATIVE_MASK);
+    fn rra(&mut self, operand: u8) -> u8 {
+        let result = self.ror(operand);
+        self.adc(result);
+        result
     }
 
-    fn bne(&mut self, operand: u8) {
-        self.branch_on_flag(operand, false, ZERO_RESULT_MASK);
+    fn rla(&mut self, operand: u8) -> u8 {
+        let result = self.rol(operand);
+        self.and(result);
+        result
     }
 
-    fn bmi(&mut self, operand: u8) {
-        self.branch_on_flag(operand, true, NEGATIVE_MASK);
+    fn lxa(&mut self, operand: u8) {
+        let value = (self.a | self.magic_constant) & operand;
+        self.a = value;
+        self.x = value;
+        self.set_zero_and_negative(value);
     }
 
-    fn bit(&mut self, operand: u8) {
-        let result = self.a & operand;
-        
-        println!("Result is {}", result);
+    fn lax(&mut self, operand: u8) {
+        self.a = operand;
+        self.x = operand;
+        self.set_zero_and_negative(operand);
+    }
 
-        self.set_flag(result == 0, ZERO_RESULT_MASK);
-        self.set_flag((result & OVERFLOW_MASK) > 0, OVERFLOW_MASK);
-        self.set_flag((result & NEGATIVE_MASK) > 0, NEGATIVE_MASK);
+    fn las(&mut self, operand: u8) {
+        let value = operand & self.sp;
+        self.a = value;
+        self.x = value;
+        self.sp = value;
+        self.set_zero_and_negative(value);
     }
 
-    fn beq(&mut self, operand: u8) {
-        self.branch_on_flag(operand, true, ZERO_RESULT_MASK);
+    fn kil(&mut self) {
+        self.jammed = true;
     }
 
-    fn bcs(&mut self, operand: u8) {
-        self.branch_on_flag(operand, true, CARRY_MASK);
+    fn isb(&mut self, operand: u8) -> u8 {
+        let result = operand.wrapping_add(1);
+        self.sbc(result);
+        result
     }
 
-    fn bcc(&mut self, operand: u8) {
-        self.branch_on_flag(operand, false, CARRY_MASK);
+    fn dcp(&mut self, operand: u8) -> u8 {
+        let result = operand.wrapping_sub(1);
+        self.cmp(result);
+        result
     }
 
-    fn asl(&mut self, operand: u8, address_mode: &AddressMode) {
-        // carry check
-        self.set_flag((operand >> 7) == 1, CARRY_MASK);
+    fn arr(&mut self, operand: u8) {
+        let value = self.a & operand;
+        let carry_in = (CARRY_MASK & self.flags) << 7;
+        let mut result = (value >> 1) | carry_in;
 
-        let temp = operand << 1;
+        // N and Z come from the rotated value and V from bits 6 and 5
+        // changing in the rotate, in both binary and decimal mode
+        self.set_zero_and_negative(result);
+        self.set_flag(((value ^ result) & OVERFLOW_MASK) > 0, OVERFLOW_MASK);
 
-        // negative check
-        self.set_flag((temp >> 7) == 1, NEGATIVE_MASK);
+        if self.flag_set(DECIMAL_MASK) {
+            if (value & LOW_NIBBLE_MASK) + (value & 0x01) > 5 {
+                result = (result & 0xF0) | (result.wrapping_add(6) & LOW_NIBBLE_MASK);
+            }
 
-        // zero check
-        self.set_flag(temp == 0, ZERO_RESULT_MASK);
+            let high_carry = (value & 0xF0) as u16 + (value & 0x10) as u16 > 0x50;
+            self.set_flag(high_carry, CARRY_MASK);
 
-        if let &AddressMode::Accumulator = address_mode {
-                self.a = temp;
+            if high_carry {
+                result = result.wrapping_add(0x60);
+            }
+        } else {
+            self.set_flag((result & 0x40) > 0, CARRY_MASK);
         }
+
+        self.a = result;
     }
 
-    fn and(&mut self, operand: u8) {
-        self.a = self.a & operand;
-       
+    fn ane(&mut self, operand: u8) {
+        self.a = (self.a | self.magic_constant) & self.x & operand;
         let temp = self.a;
+        self.set_zero_and_negative(temp);
+    }
 
-        // negative check
-        self.set_flag((temp >> 7) == 1, NEGATIVE_MASK);
+    fn anc(&mut self, operand: u8) {
+        self.and(operand);
+        let negative = self.flag_set(NEGATIVE_MASK);
+        self.set_flag(negative, CARRY_MASK);
+    }
 
-        // zero check
-        self.set_flag(temp == 0, ZERO_RESULT_MASK);
+    fn alr(&mut self, operand: u8) {
+        self.and(operand);
+        let temp = self.a;
+        self.a = self.lsr(temp);
     }
 
-    fn adc(&mut self, operand: u8) {
-        
-        let carry = CARRY_MASK & self.flags;
+    fn tya(&mut self) {
+        self.a = self.y;
+        let temp = self.a;
+        self.set_zero_and_negative(temp);
+    }
 
-        if self.flag_set(DECIMAL_MASK) {
-            let low_nibble;
-            let high_nibble;
-            let mut nibble_carry;
-
-            // TODO - do I need to validate the nibbles? What does
-            // the 6507 do when it has invalid BCD operands?
-
-            let mut temp = (LOW_NIBBLE_MASK & self.a) + (LOW_NIBBLE_MASK & operand) + carry;
-            
-            if temp <= 9 {
-                low_nibble = temp;
-                nibble_carry = 0;
-            } else {
-                low_nibble = (temp + 6) & LOW_NIBBLE_MASK; // BCD correction by adding 6
-                nibble_carry = 1;
-            };
+    fn txs(&mut self) {
+        // the only transfer that leaves the flags alone
+        self.sp = self.x;
+    }
 
-            temp = (self.a >> 4) + (operand >> 4) + nibble_carry;
-            
-            if temp <= 9 {
-                high_nibble = temp;
-                nibble_carry = 0;
-            } else {
-                high_nibble = (temp + 6) & LOW_NIBBLE_MASK; // BCD correction by adding 6
-                nibble_carry = 1;
-            };
-            
-            // carry check
-            self.set_flag(nibble_carry == 1, CARRY_MASK);
+    fn txa(&mut self) {
+        self.a = self.x;
+        let temp = self.a;
+        self.set_zero_and_negative(temp);
+    }
 
-            self.a = (high_nibble << 4) | low_nibble;
-        
-        } else {
-            let temp = self.a.wrapping_add(operand.wrapping_add(carry));
-            
-            // use u16 to easily check for carry, overload
-            let wide_result = (self.a as u16) 
-                            + (carry as u16) + (operand as u16); 
-            
-            // carry check
-            self.set_flag((wide_result > 255), CARRY_MASK);
-
-            // overflow check
-            self.set_flag((wide_result as i16) > 127 || 
-                          (wide_result as i16) < -128,
-                          OVERFLOW_MASK);
-            
-            self.a = temp;
+    fn tsx(&mut self) {
+        self.x = self.sp;
+        let temp = self.x;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn tay(&mut self) {
+        self.y = self.a;
+        let temp = self.y;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn tax(&mut self) {
+        self.x = self.a;
+        let temp = self.x;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn sei(&mut self) {
+        self.set_flag(true, INTERRUPT_DISABLE_MASK);
+    }
+
+    fn sed(&mut self) {
+        self.set_flag(true, DECIMAL_MASK);
+    }
+
+    fn sec(&mut self) {
+        self.set_flag(true, CARRY_MASK);
+    }
+
+    fn sbc(&mut self, operand: u8) {
+        let a = self.a;
+        let borrow = if self.flag_set(CARRY_MASK) { 0 } else { 1 };
+
+        // in binary mode A - M - !C is the same as A + !M + C, and on the
+        // NMOS part all four flags come from that even in decimal mode
+        self.add_binary(!operand);
+
+        if self.flag_set(DECIMAL_MASK) {
+            let mut low_nibble = (LOW_NIBBLE_MASK & a) as i16
+                               - (LOW_NIBBLE_MASK & operand) as i16 - borrow;
+
+            if low_nibble < 0 {
+                low_nibble = ((low_nibble - 6) & LOW_NIBBLE_MASK as i16) - 0x10; // BCD correction by subtracting 6
+            }
+
+            let mut temp = (a & 0xF0) as i16 - (operand & 0xF0) as i16 + low_nibble;
+
+            if temp < 0 {
+                temp -= 0x60; // BCD correction by subtracting 6 from the high digit
+            }
+
+            self.a = temp as u8;
         }
-        
+    }
+
+    fn rts<B: Bus>(&mut self, bus: &mut B) {
+        // JSR pushed the address of its own last byte, which is read
+        // again while PC is incremented past it
+        let address = self.pull_word(bus);
+        self.dummy_read(bus, address);
+        self.pc = address.wrapping_add(1);
+    }
+
+    fn rti<B: Bus>(&mut self, bus: &mut B) {
+        self.plp(bus);
+        self.pc = self.pull_word(bus);
+    }
+
+    fn ror(&mut self, operand: u8) -> u8 {
+        let carry_in = (CARRY_MASK & self.flags) << 7;
+
+        // carry check
+        self.set_flag((operand & 0x01) == 1, CARRY_MASK);
+
+        let temp = (operand >> 1) | carry_in;
+
+        self.set_zero_and_negative(temp);
+
+        temp
+    }
+
+    fn rol(&mut self, operand: u8) -> u8 {
+        let carry_in = CARRY_MASK & self.flags;
+
+        // carry check
+        self.set_flag((operand >> 7) == 1, CARRY_MASK);
+
+        let temp = (operand << 1) | carry_in;
+
+        self.set_zero_and_negative(temp);
+
+        temp
+    }
+
+    fn plp<B: Bus>(&mut self, bus: &mut B) {
+        // the break and unused bits only exist in the pushed copy
+        let value = self.pull(bus);
+        self.flags = value & !(BREAK_COMMAND_MASK | UNUSED_MASK);
+    }
+
+    fn pla<B: Bus>(&mut self, bus: &mut B) {
+        self.a = self.pull(bus);
+        let temp = self.a;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn php<B: Bus>(&mut self, bus: &mut B) {
+        let value = self.flags | BREAK_COMMAND_MASK | UNUSED_MASK;
+        self.push(bus, value);
+    }
+
+    fn pha<B: Bus>(&mut self, bus: &mut B) {
+        let value = self.a;
+        self.push(bus, value);
+    }
+
+    fn ora(&mut self, operand: u8) {
+        self.a |= operand;
+        let temp = self.a;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn nop(&mut self) {}
+
+    fn lsr(&mut self, operand: u8) -> u8 {
+        // carry check
+        self.set_flag((operand & 0x01) == 1, CARRY_MASK);
+
+        let temp = operand >> 1;
+
+        self.set_zero_and_negative(temp);
+
+        temp
+    }
+
+    fn ldy(&mut self, operand: u8) {
+        self.y = operand;
+        self.set_zero_and_negative(operand);
+    }
+
+    fn ldx(&mut self, operand: u8) {
+        self.x = operand;
+        self.set_zero_and_negative(operand);
+    }
+
+    fn lda(&mut self, operand: u8) {
+        self.a = operand;
+        self.set_zero_and_negative(operand);
+    }
+
+    fn jsr<B: Bus>(&mut self, bus: &mut B) {
+        let low_byte = self.read_byte_by_addr(bus, self.pc.wrapping_add(1)) as u16;
+        self.read_stack(bus);
+
+        // the pushed return address points at the last byte of the JSR,
+        // which isn't read until after the pushes
+        let return_address = self.pc.wrapping_add(2);
+        self.push_word(bus, return_address);
+
+        let high_byte = self.read_byte_by_addr(bus, return_address) as u16;
+        self.pc = (high_byte << 8) + low_byte;
+    }
+
+    fn jmp<B: Bus>(&mut self, bus: &mut B, address_mode: &AddressMode) {
+        self.pc = self.effective_address(bus, address_mode, false).0;
+    }
+
+    fn inc(&mut self, operand: u8) -> u8 {
+        let result = operand.wrapping_add(1);
+        self.set_zero_and_negative(result);
+        result
+    }
+
+    fn eor(&mut self, operand: u8) {
+        self.a ^= operand;
+        let temp = self.a;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn dey(&mut self) {
+        self.y = self.y.wrapping_sub(1);
+        let temp = self.y;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn dec(&mut self, operand: u8) -> u8 {
+        let result = operand.wrapping_sub(1);
+        self.set_zero_and_negative(result);
+        result
+    }
+
+    fn brk<B: Bus>(&mut self, bus: &mut B) {
+        // BRK skips a padding byte, so the return address is PC + 2
+        let return_address = self.pc.wrapping_add(2);
+        self.interrupt(bus, return_address, true);
+    }
+
+    fn iny(&mut self) {
+        self.y = self.y.wrapping_add(1);
+        let temp = self.y;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn inx(&mut self) {
+        self.x = self.x.wrapping_add(1);
+        let temp = self.x;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn dex(&mut self) {
+        self.x = self.x.wrapping_sub(1);
+        let temp = self.x;
+        self.set_zero_and_negative(temp);
+    }
+
+    fn cpy(&mut self, operand: u8) {
+        let register = self.y;
+        self.compare(register, operand);
+    }
+
+    fn cpx(&mut self, operand: u8) {
+        let register = self.x;
+        self.compare(register, operand);
+    }
+
+    fn cmp(&mut self, operand: u8) {
+        let register = self.a;
+        self.compare(register, operand);
+    }
+
+    fn clv(&mut self) {
+        self.set_flag(false, OVERFLOW_MASK);
+    }
+
+    fn cli(&mut self) {
+        self.set_flag(false, INTERRUPT_DISABLE_MASK);
+    }
+
+    fn cld(&mut self) {
+        self.set_flag(false, DECIMAL_MASK);
+    }
+
+    fn clc(&mut self) {
+        self.set_flag(false, CARRY_MASK);
+    }
+
+    fn bvs(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, true, OVERFLOW_MASK)
+    }
+    
+    fn bvc(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, false, OVERFLOW_MASK)
+    }
+
+    fn bpl(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, false, NEGATIVE_MASK)
+    }
+
+    fn bne(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, false, ZERO_RESULT_MASK)
+    }
+
+    fn bmi(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, true, NEGATIVE_MASK)
+    }
+
+    fn bit(&mut self, operand: u8) {
+        let result = self.a & operand;
+
+        // only Z depends on A; V and N are bits 6 and 7 of the operand
+        self.set_flag(result == 0, ZERO_RESULT_MASK);
+        self.set_flag((operand & OVERFLOW_MASK) > 0, OVERFLOW_MASK);
+        self.set_flag((operand & NEGATIVE_MASK) > 0, NEGATIVE_MASK);
+    }
+
+    fn beq(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, true, ZERO_RESULT_MASK)
+    }
+
+    fn bcs(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, true, CARRY_MASK)
+    }
+
+    fn bcc(&mut self, operand: u8) -> u8 {
+        self.branch_on_flag(operand, false, CARRY_MASK)
+    }
+
+    fn asl(&mut self, operand: u8) -> u8 {
+        // carry check
+        self.set_flag((operand >> 7) == 1, CARRY_MASK);
+
+        let temp = operand << 1;
+
+        // negative check
+        self.set_flag((temp >> 7) == 1, NEGATIVE_MASK);
+
+        // zero check
+        self.set_flag(temp == 0, ZERO_RESULT_MASK);
+
+        temp
+    }
+
+    fn and(&mut self, operand: u8) {
+        self.a &= operand;
+       
         let temp = self.a;
 
         // negative check
@@ -456,11 +1638,74 @@ impl Mos6507 {
         // zero check
         self.set_flag(temp == 0, ZERO_RESULT_MASK);
     }
+
+    fn adc(&mut self, operand: u8) {
+        if !self.flag_set(DECIMAL_MASK) {
+            self.add_binary(operand);
+            return;
+        }
+
+        let carry = CARRY_MASK & self.flags;
+
+        // NMOS decimal mode, which also defines what happens to invalid
+        // BCD digits: the low digit is corrected and carried first...
+        let mut low_nibble = (LOW_NIBBLE_MASK & self.a) + (LOW_NIBBLE_MASK & operand) + carry;
+
+        if low_nibble > 9 {
+            low_nibble = ((low_nibble + 6) & LOW_NIBBLE_MASK) + 0x10; // BCD correction by adding 6
+        }
+
+        let mut temp = (self.a & 0xF0) as u16 + (operand & 0xF0) as u16 + low_nibble as u16;
+
+        // ...then N and V come from the sum before the high digit is
+        // corrected, while Z comes from the plain binary sum
+        let binary_result = self.a.wrapping_add(operand).wrapping_add(carry);
+        self.set_flag(binary_result == 0, ZERO_RESULT_MASK);
+        self.set_flag((temp & 0x80) != 0, NEGATIVE_MASK);
+        self.set_flag(((self.a ^ temp as u8) & !(self.a ^ operand) & 0x80) != 0, OVERFLOW_MASK);
+
+        if temp >= 0xA0 {
+            temp += 0x60; // BCD correction by adding 6 to the high digit
+        }
+
+        // carry check
+        self.set_flag(temp > 0xFF, CARRY_MASK);
+
+        self.a = temp as u8;
+    }
+
+    // A + M + C, setting the flags the way ADC does in binary mode
+    fn add_binary(&mut self, operand: u8) {
+        let carry = CARRY_MASK & self.flags;
+        let temp = self.a.wrapping_add(operand.wrapping_add(carry));
+        
+        // use u16 to easily check for carry, overload
+        let wide_result = (self.a as u16) 
+                        + (carry as u16) + (operand as u16); 
+        
+        // carry check
+        self.set_flag(wide_result > 255, CARRY_MASK);
+
+        // overflow check, the operands have the same sign but the
+        // result doesn't
+        self.set_flag(((self.a ^ temp) & (operand ^ temp) & 0x80) != 0, OVERFLOW_MASK);
+        
+        self.a = temp;
+
+        // negative check
+        self.set_flag((temp >> 7) == 1, NEGATIVE_MASK);
+
+        // zero check
+        self.set_flag(temp == 0, ZERO_RESULT_MASK);
+    }
 }
 
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use bus::{AtariBus, Bus, FlatRam};
+    use cartridge::Cartridge;
     
     #[test]
     fn adc() {
@@ -469,10 +1714,10 @@ mod tests {
         cpu.adc(1);
 
         assert_eq!(cpu.a, 1);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), false);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     }
 
     #[test]
@@ -483,10 +1728,10 @@ mod tests {
         cpu.adc(1);
 
         assert_eq!(cpu.a, 2);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), false);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     }
     
     #[test]
@@ -496,11 +1741,12 @@ mod tests {
 
         cpu.adc(1);
 
+        // -1 + 1 doesn't overflow as a signed add
         assert_eq!(cpu.a, 0);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), true);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     }
    
     #[test]
@@ -511,10 +1757,10 @@ mod tests {
         cpu.adc(1);
 
         assert_eq!(cpu.a, 128);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), true);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     }
    
      #[test]
@@ -526,10 +1772,10 @@ mod tests {
         cpu.adc(35); // '23' in BCD
 
         assert_eq!(cpu.a, 52); // '34' in BCD
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), false);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     }
     
     #[test]
@@ -541,25 +1787,66 @@ mod tests {
         cpu.adc(38); // '26' in BCD
 
         assert_eq!(cpu.a, 97); // '61' in BCD
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), false);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     }
 
     #[test]
-    fn adc_decimal_carry_flag_zero_flag() {
+    fn adc_decimal_carry_flag_nmos_flags() {
         let mut cpu = Mos6507::new();
         cpu.flags = 0x08;
         cpu.a = 71; // '47' in BCD
 
         cpu.adc(83); // '53' in BCD
 
+        // N and V come from the uncorrected $A0 and Z from the binary $9A
         assert_eq!(cpu.a, 0); // '00' in BCD
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), false);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn adc_decimal_invalid_digits() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::DECIMAL_MASK;
+        cpu.a = 0x0F;
+
+        cpu.adc(0x0F);
+
+        assert_eq!(cpu.a, 0x14);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+    }
+
+    #[test]
+    fn adc_decimal_zero_flag() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::DECIMAL_MASK;
+        cpu.a = 0x99;
+
+        cpu.adc(0x67);
+
+        // the binary sum is $00 even though the decimal one is $66
+        assert_eq!(cpu.a, 0x66);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn adc_decimal_exhaustive() {
+        for (a, operand, carry) in decimal_inputs() {
+            let mut cpu = Mos6507::new();
+            cpu.flags = super::DECIMAL_MASK | carry;
+            cpu.a = a;
+
+            cpu.adc(operand);
+
+            assert_eq!((cpu.a, cpu.flags), reference_adc_decimal(a, operand, carry),
+                       "{:02X} + {:02X} + {}", a, operand, carry);
+        }
     }
     
     #[test]
@@ -570,8 +1857,8 @@ mod tests {
         cpu.and(1);
 
         assert_eq!(cpu.a, 1);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
     #[test]
@@ -582,8 +1869,8 @@ mod tests {
         cpu.and(0);
 
         assert_eq!(cpu.a, 0);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
     #[test]
@@ -594,8 +1881,8 @@ mod tests {
         cpu.and(128); // 128 unsigned has leftmost (negative) bit 1
 
         assert_eq!(cpu.a, 128);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
     #[test]
@@ -603,12 +1890,12 @@ mod tests {
         let mut cpu = Mos6507::new();
         cpu.a = 128; // 128 unsigned has leftmost (negative) bit 1
 
-        cpu.asl(128, &super::AddressMode::Accumulator);
+        cpu.a = cpu.asl(128);
 
         assert_eq!(cpu.a, 0);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
     
     #[test]
@@ -616,12 +1903,12 @@ mod tests {
         let mut cpu = Mos6507::new();
         cpu.a = 32;
 
-        cpu.asl(32, &super::AddressMode::Accumulator);
+        cpu.a = cpu.asl(32);
 
         assert_eq!(cpu.a, 64);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
     
     #[test]
@@ -629,12 +1916,12 @@ mod tests {
         let mut cpu = Mos6507::new();
         cpu.a = 64;
 
-        cpu.asl(64, &super::AddressMode::Accumulator);
+        cpu.a = cpu.asl(64);
 
         assert_eq!(cpu.a, 128);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
     
     #[test]
@@ -642,12 +1929,12 @@ mod tests {
         let mut cpu = Mos6507::new();
         cpu.a = 0;
 
-        cpu.asl(0, &super::AddressMode::Accumulator);
+        cpu.a = cpu.asl(0);
 
         assert_eq!(cpu.a, 0);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
    
     #[test]
@@ -655,12 +1942,12 @@ mod tests {
         let mut cpu = Mos6507::new();
         cpu.a = 10;
 
-        cpu.asl(192, &super::AddressMode::ZeroPage);
+        cpu.asl(192);
 
         assert_eq!(cpu.a, 10);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
     #[test]
@@ -775,9 +2062,9 @@ mod tests {
 
         cpu.bit(operand);
 
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+        assert!(cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
     }
 
     #[test]
@@ -927,11 +2214,23 @@ mod tests {
 
         cpu.bit(operand);
 
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
     }
     
+    #[test]
+    fn bit_flags_from_operand() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x01;
+
+        cpu.bit(0xC0);
+
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+        assert!(cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
     #[test]
     fn bne() {
         let mut cpu = Mos6507::new();
@@ -967,6 +2266,43 @@ mod tests {
         assert_eq!(cpu.pc, 1);
     }
 
+    #[test]
+    fn bne_cycles() {
+        let mut cpu = Mos6507::new();
+        cpu.pc = 0x1000;
+
+        assert_eq!(cpu.bne(0x10), 1);
+        assert_eq!(cpu.pc, 0x1010);
+    }
+
+    #[test]
+    fn bne_cycles_page_crossed() {
+        let mut cpu = Mos6507::new();
+        cpu.pc = 0x10F0;
+
+        assert_eq!(cpu.bne(0x20), 2);
+        assert_eq!(cpu.pc, 0x1110);
+    }
+
+    #[test]
+    fn bne_cycles_not_taken() {
+        let mut cpu = Mos6507::new();
+        cpu.pc = 0x10F0;
+        cpu.set_flag(true, super::ZERO_RESULT_MASK);
+
+        assert_eq!(cpu.bne(0x20), 0);
+        assert_eq!(cpu.pc, 0x10F0);
+    }
+
+    #[test]
+    fn bne_most_negative_operand() {
+        let mut cpu = Mos6507::new();
+        cpu.pc = 0x1080;
+
+        assert_eq!(cpu.bne(0x80), 1);
+        assert_eq!(cpu.pc, 0x1000);
+    }
+
     #[test]
     fn bpl() {
         let mut cpu = Mos6507::new();
@@ -990,6 +2326,25 @@ mod tests {
         assert_eq!(cpu.pc, 0);
     }
 
+    #[test]
+    fn brk() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(0xFFFE, &[0x00, 0x20]);
+        cpu.pc = 0x1000;
+        cpu.sp = 0xFF;
+        cpu.flags = super::CARRY_MASK;
+
+        cpu.brk(&mut bus);
+
+        assert_eq!(cpu.pc, 0x2000);
+        assert_eq!(cpu.sp, 0xFC);
+        assert_eq!(bus.read(0x01FF), 0x10);
+        assert_eq!(bus.read(0x01FE), 0x02);
+        assert_eq!(bus.read(0x01FD), super::CARRY_MASK | super::BREAK_COMMAND_MASK | super::UNUSED_MASK);
+        assert!(cpu.flag_set(super::INTERRUPT_DISABLE_MASK));
+    }
+
     #[test]
     fn bvc() {
         let mut cpu = Mos6507::new();
@@ -1044,9 +2399,9 @@ mod tests {
         cpu.cmp(128);
 
         assert_eq!(cpu.a, 128);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     }
     
     #[test]
@@ -1057,9 +2412,9 @@ mod tests {
         cpu.cmp(33);
 
         assert_eq!(cpu.a, 32);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
    
     #[test]
@@ -1070,9 +2425,9 @@ mod tests {
         cpu.cpx(100);
 
         assert_eq!(cpu.x, 100);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     }
     
     #[test]
@@ -1083,9 +2438,9 @@ mod tests {
         cpu.cpx(33);
 
         assert_eq!(cpu.x, 32);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
     
     #[test]
@@ -1096,9 +2451,9 @@ mod tests {
         cpu.cpx(32);
 
         assert_eq!(cpu.x, 33);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
     #[test]
@@ -1109,9 +2464,9 @@ mod tests {
         cpu.cpy(100);
 
         assert_eq!(cpu.y, 100);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     }
     
     #[test]
@@ -1122,9 +2477,9 @@ mod tests {
         cpu.cpy(33);
 
         assert_eq!(cpu.y, 32);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
     
     #[test]
@@ -1135,9 +2490,9 @@ mod tests {
         cpu.cpy(32);
 
         assert_eq!(cpu.y, 33);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
     #[test]
@@ -1148,33 +2503,79 @@ mod tests {
         cpu.cmp(32);
 
         assert_eq!(cpu.a, 33);
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
  
     #[test]
-    fn dex() {
+    fn cmp_unsigned() {
         let mut cpu = Mos6507::new();
-        cpu.x = 23;
+        cpu.a = 0xFF;
 
-        cpu.dex();
+        cpu.cmp(0x01);
+
+        // $FF is above $01 unsigned, even though it's -1 signed
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
 
-        assert_eq!(cpu.x, 22);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
-    } 
-   
     #[test]
-    fn dex_zero_flag_set() {
+    fn dec() {
         let mut cpu = Mos6507::new();
-        cpu.x = 1;
+
+        let result = cpu.dec(23);
+
+        assert_eq!(result, 22);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn dec_zero_flag_set() {
+        let mut cpu = Mos6507::new();
+
+        let result = cpu.dec(1);
+
+        assert_eq!(result, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn dec_negative_flag_set() {
+        let mut cpu = Mos6507::new();
+
+        let result = cpu.dec(0);
+
+        assert_eq!(result, 255);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn dex() {
+        let mut cpu = Mos6507::new();
+        cpu.x = 23;
+
+        cpu.dex();
+
+        assert_eq!(cpu.x, 22);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    } 
+   
+    #[test]
+    fn dex_zero_flag_set() {
+        let mut cpu = Mos6507::new();
+        cpu.x = 1;
 
         cpu.dex();
 
         assert_eq!(cpu.x, 0);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
    
     #[test]
@@ -1185,10 +2586,115 @@ mod tests {
         cpu.dex();
 
         assert_eq!(cpu.x, 255);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
+    #[test]
+    fn dey() {
+        let mut cpu = Mos6507::new();
+        cpu.y = 23;
+
+        cpu.dey();
+
+        assert_eq!(cpu.y, 22);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn dey_zero_flag_set() {
+        let mut cpu = Mos6507::new();
+        cpu.y = 1;
+
+        cpu.dey();
+
+        assert_eq!(cpu.y, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn dey_negative_flag_set() {
+        let mut cpu = Mos6507::new();
+        cpu.y = 0;
+
+        cpu.dey();
+
+        assert_eq!(cpu.y, 255);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn eor() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x0F;
+
+        cpu.eor(0x05);
+
+        assert_eq!(cpu.a, 0x0A);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn eor_zero() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x5A;
+
+        cpu.eor(0x5A);
+
+        assert_eq!(cpu.a, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn eor_negative() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x7F;
+
+        cpu.eor(0xFF);
+
+        assert_eq!(cpu.a, 0x80);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn inc() {
+        let mut cpu = Mos6507::new();
+
+        let result = cpu.inc(23);
+
+        assert_eq!(result, 24);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn inc_zero_flag_set() {
+        let mut cpu = Mos6507::new();
+
+        let result = cpu.inc(255);
+
+        assert_eq!(result, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn inc_negative_flag_set() {
+        let mut cpu = Mos6507::new();
+
+        let result = cpu.inc(127);
+
+        assert_eq!(result, 128);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
     #[test]
     fn inx() {
         let mut cpu = Mos6507::new();
@@ -1197,8 +2703,8 @@ mod tests {
         cpu.inx();
 
         assert_eq!(cpu.x, 24);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
    
     #[test]
@@ -1209,8 +2715,8 @@ mod tests {
         cpu.inx();
 
         assert_eq!(cpu.x, 0);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
    
     #[test]
@@ -1221,10 +2727,21 @@ mod tests {
         cpu.inx();
 
         assert_eq!(cpu.x, 255);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
+    #[test]
+    fn inx_signed_overflow() {
+        let mut cpu = Mos6507::new();
+        cpu.x = 0x7F;
+
+        cpu.inx();
+
+        assert_eq!(cpu.x, 0x80);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
     #[test]
     fn iny() {
         let mut cpu = Mos6507::new();
@@ -1233,8 +2750,8 @@ mod tests {
         cpu.iny();
 
         assert_eq!(cpu.y, 24);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
    
     #[test]
@@ -1245,8 +2762,8 @@ mod tests {
         cpu.iny();
 
         assert_eq!(cpu.y, 0);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), false);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
    
     #[test]
@@ -1257,23 +2774,1769 @@ mod tests {
         cpu.iny();
 
         assert_eq!(cpu.y, 255);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
     } 
 
+    #[test]
+    fn jsr() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(0x1000, &[0x20, 0x00, 0x30]);
+        cpu.pc = 0x1000;
+        cpu.sp = 0xFF;
+
+        cpu.jsr(&mut bus);
+
+        assert_eq!(cpu.pc, 0x3000);
+        assert_eq!(cpu.sp, 0xFD);
+        assert_eq!(bus.read(0x01FF), 0x10);
+        assert_eq!(bus.read(0x01FE), 0x02);
+    }
+
+    #[test]
+    fn lda() {
+        let mut cpu = Mos6507::new();
+
+        cpu.lda(23);
+
+        assert_eq!(cpu.a, 23);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn lda_zero_flag_set() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 23;
+
+        cpu.lda(0);
+
+        assert_eq!(cpu.a, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn lda_negative_flag_set() {
+        let mut cpu = Mos6507::new();
+
+        cpu.lda(128);
+
+        assert_eq!(cpu.a, 128);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ldx() {
+        let mut cpu = Mos6507::new();
+
+        cpu.ldx(23);
+
+        assert_eq!(cpu.x, 23);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ldx_negative_flag_set() {
+        let mut cpu = Mos6507::new();
+
+        cpu.ldx(255);
+
+        assert_eq!(cpu.x, 255);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ldy() {
+        let mut cpu = Mos6507::new();
+
+        cpu.ldy(23);
+
+        assert_eq!(cpu.y, 23);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ldy_zero_flag_set() {
+        let mut cpu = Mos6507::new();
+        cpu.y = 23;
+
+        cpu.ldy(0);
+
+        assert_eq!(cpu.y, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn lsr() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 1;
+
+        cpu.a = cpu.lsr(1);
+
+        assert_eq!(cpu.a, 0);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn lsr_no_carry_no_zero() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 128;
+
+        cpu.a = cpu.lsr(128);
+
+        assert_eq!(cpu.a, 64);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn lsr_no_accumulator() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 10;
+
+        let result = cpu.lsr(3);
+
+        assert_eq!(result, 1);
+        assert_eq!(cpu.a, 10);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ora() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x0F;
+
+        cpu.ora(0x30);
+
+        assert_eq!(cpu.a, 0x3F);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ora_zero() {
+        let mut cpu = Mos6507::new();
+
+        cpu.ora(0);
+
+        assert_eq!(cpu.a, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ora_negative() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x01;
+
+        cpu.ora(0x80);
+
+        assert_eq!(cpu.a, 0x81);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn pha() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        cpu.sp = 0xFF;
+        cpu.a = 0x42;
+
+        cpu.pha(&mut bus);
+
+        assert_eq!(cpu.sp, 0xFE);
+        assert_eq!(bus.read(0x01FF), 0x42);
+    }
+
+    #[test]
+    fn php() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        cpu.sp = 0x00;
+        cpu.flags = super::NEGATIVE_MASK;
+
+        cpu.php(&mut bus);
+
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(bus.read(0x0100), super::NEGATIVE_MASK | super::BREAK_COMMAND_MASK | super::UNUSED_MASK);
+    }
+
+    #[test]
+    fn pla() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.write(0x01FF, 0x80);
+        cpu.sp = 0xFE;
+        cpu.a = 23;
+
+        cpu.pla(&mut bus);
+
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(cpu.a, 0x80);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn pla_zero_flag_set() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        cpu.sp = 0xFE;
+        cpu.a = 23;
+
+        cpu.pla(&mut bus);
+
+        assert_eq!(cpu.a, 0);
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn plp() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.write(0x0100, 0xFF);
+        cpu.sp = 0xFF;
+
+        cpu.plp(&mut bus);
+
+        // the break and unused bits never make it into the register
+        assert_eq!(cpu.sp, 0x00);
+        assert_eq!(cpu.flags, !(super::BREAK_COMMAND_MASK | super::UNUSED_MASK));
+    }
+
+    #[test]
+    fn rol() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x40;
+        cpu.set_flag(true, super::CARRY_MASK);
+
+        cpu.a = cpu.rol(0x40);
+
+        assert_eq!(cpu.a, 0x81);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn rol_carry_flag_zero_flag() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x80;
+
+        cpu.a = cpu.rol(0x80);
+
+        assert_eq!(cpu.a, 0);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ror() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x02;
+        cpu.set_flag(true, super::CARRY_MASK);
+
+        cpu.a = cpu.ror(0x02);
+
+        assert_eq!(cpu.a, 0x81);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ror_no_accumulator() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 10;
+
+        let result = cpu.ror(0x01);
+
+        assert_eq!(result, 0);
+        assert_eq!(cpu.a, 10);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn rti() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(0x01FD, &[super::CARRY_MASK, 0x34, 0x12]);
+        cpu.sp = 0xFC;
+
+        cpu.rti(&mut bus);
+
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(cpu.pc, 0x1234);
+        assert_eq!(cpu.flags, super::CARRY_MASK);
+    }
+
+    #[test]
+    fn rts() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(0x01FE, &[0x02, 0x10]);
+        cpu.sp = 0xFD;
+
+        cpu.rts(&mut bus);
+
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(cpu.pc, 0x1003);
+    }
+
+    #[test]
+    fn sbc() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::CARRY_MASK;
+        cpu.a = 5;
+
+        cpu.sbc(3);
+
+        assert_eq!(cpu.a, 2);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn sbc_with_borrow() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 5;
+
+        cpu.sbc(3);
+
+        assert_eq!(cpu.a, 1);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn sbc_carry_flag_clear_negative_flag() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::CARRY_MASK;
+        cpu.a = 3;
+
+        cpu.sbc(5);
+
+        assert_eq!(cpu.a, 254);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn sbc_decimal() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::DECIMAL_MASK | super::CARRY_MASK;
+        cpu.a = 0x42; // '42' in BCD
+
+        cpu.sbc(0x13); // '13' in BCD
+
+        assert_eq!(cpu.a, 0x29); // '29' in BCD
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn sbc_decimal_borrow() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::DECIMAL_MASK | super::CARRY_MASK;
+        cpu.a = 0x10; // '10' in BCD
+
+        cpu.sbc(0x20); // '20' in BCD
+
+        assert_eq!(cpu.a, 0x90); // '90' in BCD, borrowing from the next column
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+    }
+
+    #[test]
+    fn sbc_decimal_invalid_digits() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::DECIMAL_MASK | super::CARRY_MASK;
+        cpu.a = 0x00;
+
+        cpu.sbc(0x0F);
+
+        assert_eq!(cpu.a, 0x9B);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+    }
+
+    #[test]
+    fn sbc_decimal_exhaustive() {
+        for (a, operand, carry) in decimal_inputs() {
+            let mut cpu = Mos6507::new();
+            cpu.flags = super::DECIMAL_MASK | carry;
+            cpu.a = a;
+
+            cpu.sbc(operand);
+
+            assert_eq!((cpu.a, cpu.flags), reference_sbc_decimal(a, operand, carry),
+                       "{:02X} - {:02X} - {}", a, operand, 1 - carry);
+        }
+    }
+
+    #[test]
+    fn sec() {
+        let mut cpu = Mos6507::new();
+
+        cpu.sec();
+
+        assert_eq!(cpu.flags, super::CARRY_MASK);
+    }
+
+    #[test]
+    fn sed() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::CARRY_MASK;
+
+        cpu.sed();
+
+        assert_eq!(cpu.flags, super::CARRY_MASK | super::DECIMAL_MASK);
+    }
+
+    #[test]
+    fn sei() {
+        let mut cpu = Mos6507::new();
+
+        cpu.sei();
+
+        assert_eq!(cpu.flags, super::INTERRUPT_DISABLE_MASK);
+    }
+
+    #[test]
+    fn tax() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 128;
+
+        cpu.tax();
+
+        assert_eq!(cpu.x, 128);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn tay() {
+        let mut cpu = Mos6507::new();
+        cpu.y = 23;
+
+        cpu.tay();
+
+        assert_eq!(cpu.y, 0);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn tsx() {
+        let mut cpu = Mos6507::new();
+        cpu.sp = 0xFD;
+
+        cpu.tsx();
+
+        assert_eq!(cpu.x, 0xFD);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn txa() {
+        let mut cpu = Mos6507::new();
+        cpu.x = 23;
+
+        cpu.txa();
+
+        assert_eq!(cpu.a, 23);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn txs() {
+        let mut cpu = Mos6507::new();
+        cpu.x = 0;
+
+        cpu.txs();
+
+        // TXS is the one transfer that leaves the flags untouched
+        assert_eq!(cpu.sp, 0);
+        assert_eq!(cpu.flags, 0);
+    }
+
+    #[test]
+    fn tya() {
+        let mut cpu = Mos6507::new();
+        cpu.y = 0xFF;
+
+        cpu.tya();
+
+        assert_eq!(cpu.a, 0xFF);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn alr() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0xFF;
+
+        cpu.alr(0x03);
+
+        assert_eq!(cpu.a, 0x01);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn anc() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0xFF;
+
+        cpu.anc(0x80);
+
+        assert_eq!(cpu.a, 0x80);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn ane() {
+        let mut cpu = Mos6507::new();
+        cpu.x = 0x0F;
+
+        cpu.ane(0xFF);
+
+        assert_eq!(cpu.a, super::DEFAULT_MAGIC_CONSTANT & 0x0F);
+    }
+
+    #[test]
+    fn ane_magic_constant() {
+        let mut cpu = Mos6507::new();
+        cpu.set_magic_constant(0x00);
+        cpu.x = 0xFF;
+
+        cpu.ane(0xFF);
+
+        assert_eq!(cpu.a, 0);
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn arr() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x40;
+
+        cpu.arr(0xFF);
+
+        assert_eq!(cpu.a, 0x20);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn arr_carry_in() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::CARRY_MASK;
+        cpu.a = 0xFF;
+
+        cpu.arr(0xFF);
+
+        assert_eq!(cpu.a, 0xFF);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn arr_decimal() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::DECIMAL_MASK;
+        cpu.a = 0xFF;
+
+        cpu.arr(0xFF);
+
+        assert_eq!(cpu.a, 0xD5);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn dcp() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 5;
+
+        let result = cpu.dcp(6);
+
+        assert_eq!(result, 5);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn isb() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::CARRY_MASK;
+        cpu.a = 5;
+
+        let result = cpu.isb(2);
+
+        assert_eq!(result, 3);
+        assert_eq!(cpu.a, 2);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+    }
+
+    #[test]
+    fn kil() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        cpu.pc = 0x1234;
+
+        cpu.kil();
+        cpu.execute_instruction(&mut bus);
+
+        assert!(cpu.is_jammed());
+        assert_eq!(cpu.pc, 0x1234);
+    }
+
+    #[test]
+    fn las() {
+        let mut cpu = Mos6507::new();
+        cpu.sp = 0xF0;
+
+        cpu.las(0x3F);
+
+        assert_eq!(cpu.a, 0x30);
+        assert_eq!(cpu.x, 0x30);
+        assert_eq!(cpu.sp, 0x30);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn lax() {
+        let mut cpu = Mos6507::new();
+
+        cpu.lax(0x80);
+
+        assert_eq!(cpu.a, 0x80);
+        assert_eq!(cpu.x, 0x80);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn lxa() {
+        let mut cpu = Mos6507::new();
+        cpu.set_magic_constant(0xFF);
+
+        cpu.lxa(0x7F);
+
+        assert_eq!(cpu.a, 0x7F);
+        assert_eq!(cpu.x, 0x7F);
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn rla() {
+        let mut cpu = Mos6507::new();
+        cpu.flags = super::CARRY_MASK;
+        cpu.a = 0xFF;
+
+        let result = cpu.rla(0x40);
+
+        assert_eq!(result, 0x81);
+        assert_eq!(cpu.a, 0x81);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn rra() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 1;
+
+        let result = cpu.rra(0x03);
+
+        // the carry rotated out of the operand feeds the addition
+        assert_eq!(result, 0x01);
+        assert_eq!(cpu.a, 3);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+    }
+
+    #[test]
+    fn sbx() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0xFF;
+        cpu.x = 0x0F;
+
+        cpu.sbx(0x05);
+
+        assert_eq!(cpu.x, 0x0A);
+        assert_eq!(cpu.a, 0xFF);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn sbx_borrow() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x01;
+        cpu.x = 0xFF;
+
+        cpu.sbx(0x02);
+
+        assert_eq!(cpu.x, 0xFF);
+        assert!(!cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn slo() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0x01;
+
+        let result = cpu.slo(0x80);
+
+        assert_eq!(result, 0x00);
+        assert_eq!(cpu.a, 0x01);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn sre() {
+        let mut cpu = Mos6507::new();
+        cpu.a = 0xFF;
+
+        let result = cpu.sre(0x03);
+
+        assert_eq!(result, 0x01);
+        assert_eq!(cpu.a, 0xFE);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn execute_instruction_cycles() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+
+        // with nothing mapped every fetch reads a BRK
+        assert_eq!(cpu.execute_instruction(&mut bus), 7);
+        assert_eq!(cpu.execute_instruction(&mut bus), 7);
+        assert_eq!(cpu.cycles(), 14);
+    }
+
+    #[test]
+    fn execute_instruction_cycles_jammed() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+
+        cpu.kil();
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 1);
+        assert_eq!(cpu.cycles(), 1);
+    }
+
+    #[test]
+    fn page_penalty() {
+        assert!(OPCODES[0xBD].has_page_penalty()); // LDA abs,X
+        assert!(OPCODES[0xB1].has_page_penalty()); // LDA (zp),Y
+        assert!(!OPCODES[0x9D].has_page_penalty()); // STA abs,X
+        assert!(!OPCODES[0x1E].has_page_penalty()); // ASL abs,X
+        assert!(!OPCODES[0xB5].has_page_penalty()); // LDA zp,X
+    }
+
+    #[test]
+    fn opcode_table() {
+        assert_eq!(OPCODES[0xD0].mnemonic, "BNE");
+        assert_eq!(OPCODES[0x70].address_mode, AddressMode::Relative);
+        assert_eq!(OPCODES[0x6C].bytes, 3);
+        assert_eq!(OPCODES[0x0A].bytes, 1);
+        assert_eq!(OPCODES[0xFE].cycles, 7);
+    }
+
+    #[test]
+    fn bne_dispatch() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xD0, 0x10]); // BNE +$10
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.pc, 0x0212);
+    }
+
+    #[test]
+    fn rol_accumulator() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x2A]); // ROL A
+        cpu.flags = super::CARRY_MASK;
+        cpu.a = 0x80;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.a, 0x01);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+    }
+
+    // Records every address read so tests can check for stray accesses
+    struct RecordingBus {
+        ram: FlatRam,
+        reads: Vec<u16>,
+    }
+
+    impl Bus for RecordingBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.reads.push(address);
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.ram.write(address, data);
+        }
+    }
+
+    fn program(address: u16, code: &[u8]) -> (Mos6507, FlatRam) {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(address, code);
+        cpu.pc = address;
+        (cpu, bus)
+    }
+
+    #[test]
+    fn immediate() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xA9, 0x42]); // LDA #$42
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.a, 0x42);
+        assert_eq!(cpu.pc, 0x0202);
+    }
+
+    #[test]
+    fn zero_page_x_wraps() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xB5, 0xF8]); // LDA $F8,X
+        bus.write(0x0008, 0x42);
+        bus.write(0x0108, 0x24);
+        cpu.x = 0x10;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.a, 0x42);
+    }
+
+    #[test]
+    fn zero_page_y_wraps() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xB6, 0xF8]); // LDX $F8,Y
+        bus.write(0x0008, 0x42);
+        cpu.y = 0x10;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.x, 0x42);
+    }
+
+    #[test]
+    fn absolute_y() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xB9, 0xFF, 0x30]); // LDA $30FF,Y
+        bus.write(0x3101, 0x42);
+        cpu.y = 2;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.a, 0x42);
+        assert_eq!(cpu.pc, 0x0203);
+    }
+
+    #[test]
+    fn indirect_x_pointer_wraps() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xA1, 0xFE]); // LDA ($FE,X)
+        bus.load(0x00FF, &[0x34]);
+        bus.load(0x0000, &[0x12]);
+        bus.write(0x1234, 0x42);
+        cpu.x = 1;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.a, 0x42);
+    }
+
+    #[test]
+    fn indirect_y_pointer_wraps() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xB1, 0xFF]); // LDA ($FF),Y
+        bus.load(0x00FF, &[0x34]);
+        bus.load(0x0000, &[0x12]);
+        bus.write(0x1236, 0x42);
+        cpu.y = 2;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.a, 0x42);
+    }
+
+    #[test]
+    fn jmp_absolute() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x4C, 0x34, 0x12]); // JMP $1234
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.pc, 0x1234);
+    }
+
+    #[test]
+    fn jmp_indirect_page_wrap() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x6C, 0xFF, 0x10]); // JMP ($10FF)
+        bus.write(0x10FF, 0x34);
+        bus.write(0x1000, 0x12);
+        bus.write(0x1100, 0x56);
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.pc, 0x1234);
+    }
+
+    #[test]
+    fn jsr_rts() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x20, 0x00, 0x03]); // JSR $0300
+        bus.write(0x0300, 0x60); // RTS
+        cpu.sp = 0xFF;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.pc, 0x0300);
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.pc, 0x0203);
+        assert_eq!(cpu.sp, 0xFF);
+    }
+
+    #[test]
+    fn asl_accumulator() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x0A]); // ASL A
+        cpu.a = 0x41;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.a, 0x82);
+        assert_eq!(cpu.pc, 0x0201);
+    }
+
+    #[test]
+    fn asl_writes_back() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x06, 0x10]); // ASL $10
+        bus.write(0x0010, 0x41);
+        cpu.a = 0x01;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(bus.read(0x0010), 0x82);
+        assert_eq!(cpu.a, 0x01);
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
+    }
+
+    #[test]
+    fn inc_writes_back() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xFE, 0x00, 0x30]); // INC $3000,X
+        bus.write(0x3005, 0xFF);
+        cpu.x = 5;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(bus.read(0x3005), 0x00);
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+    }
+
+    #[test]
+    fn sta_indirect_y() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x91, 0x80]); // STA ($80),Y
+        bus.load(0x0080, &[0x00, 0x30]);
+        cpu.a = 0x42;
+        cpu.y = 0x10;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(bus.read(0x3010), 0x42);
+        assert_eq!(cpu.pc, 0x0202);
+    }
+
+    #[test]
+    fn sta_does_not_read_target() {
+        let (mut cpu, ram) = program(0x0200, &[0x8D, 0x84, 0x02]); // STA $0284
+        let mut bus = RecordingBus { ram, reads: Vec::new() };
+        cpu.a = 0x42;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert!(!bus.reads.contains(&0x0284));
+        assert_eq!(bus.ram.read(0x0284), 0x42);
+    }
+
+    #[test]
+    fn page_crossed_cycles() {
+        let (mut cpu, mut bus) = program(0x0200, &[0xBD, 0xFF, 0x10, 0xBD, 0xFF, 0x10]); // LDA $10FF,X
+        cpu.x = 0;
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 4);
+
+        cpu.x = 1;
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 5);
+    }
+
+    // Records every access and tick, so tests can check what happens on
+    // each cycle
+    struct CycleRecordingBus {
+        ram: FlatRam,
+        accesses: Vec<(u16, bool)>,
+        ticks: usize,
+    }
+
+    impl Bus for CycleRecordingBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.accesses.push((address, false));
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.accesses.push((address, true));
+            self.ram.write(address, data);
+        }
+
+        fn tick(&mut self, cycles: u8) {
+            self.ticks += cycles as usize;
+        }
+    }
+
+    fn cycle_program(code: &[u8]) -> (Mos6507, CycleRecordingBus) {
+        let (cpu, ram) = program(0x0200, code);
+        (cpu, CycleRecordingBus { ram, accesses: Vec::new(), ticks: 0 })
+    }
+
+    #[test]
+    fn one_access_per_cycle() {
+        // with and without page crossings and taken branches
+        for &(index, flags) in [(0x00, 0x00), (0x01, 0xFF)].iter() {
+            for opcode in 0..256u16 {
+                let (mut cpu, mut bus) = cycle_program(&[opcode as u8, 0xFF, 0x10]);
+                bus.ram.load(0x00FF, &[0xFF, 0x10]);
+                cpu.x = index;
+                cpu.y = index;
+                cpu.flags = flags;
+                cpu.sp = 0xFD;
+
+                let cycles = cpu.execute_instruction(&mut bus);
+
+                assert_eq!(bus.accesses.len(), cycles as usize, "opcode {:02X}", opcode);
+            }
+        }
+    }
+
+    #[test]
+    fn modify_dummy_accesses() {
+        let (mut cpu, mut bus) = cycle_program(&[0xFE, 0xFF, 0x10]); // INC $10FF,X
+        cpu.x = 1;
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(bus.accesses, vec![(0x0200, false), (0x0201, false), (0x0202, false),
+                                      (0x1000, false), (0x1100, false),
+                                      (0x1100, true), (0x1100, true)]);
+    }
+
+    #[test]
+    fn branch_dummy_reads() {
+        let (mut cpu, mut bus) = cycle_program(&[0xD0, 0x7F]); // BNE +$7F
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(bus.accesses, vec![(0x0200, false), (0x0201, false), (0x0202, false)]);
+        assert_eq!(cpu.pc, 0x0281);
+
+        let (mut cpu, mut bus) = cycle_program(&[0xD0, 0x80]); // BNE -$80
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(bus.accesses[3], (0x0282, false));
+        assert_eq!(cpu.pc, 0x0182);
+    }
+
+    #[test]
+    fn jsr_rts_accesses() {
+        let (mut cpu, mut bus) = cycle_program(&[0x20, 0x00, 0x03]); // JSR $0300
+        bus.ram.write(0x0300, 0x60); // RTS
+        cpu.sp = 0xFF;
+
+        cpu.execute_instruction(&mut bus);
+
+        // the high byte of the target is read after the pushes
+        assert_eq!(bus.accesses, vec![(0x0200, false), (0x0201, false), (0x01FF, false),
+                                      (0x01FF, true), (0x01FE, true), (0x0202, false)]);
+
+        bus.accesses.clear();
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(bus.accesses, vec![(0x0300, false), (0x0301, false), (0x01FD, false),
+                                      (0x01FE, false), (0x01FF, false), (0x0202, false)]);
+        assert_eq!(cpu.pc, 0x0203);
+    }
+
+    #[test]
+    fn tick() {
+        let (mut cpu, mut bus) = cycle_program(&[0xEE, 0x80, 0x00]); // INC $0080
+
+        let mut cycles = 1;
+        while !cpu.tick(&mut bus) {
+            // one access and one tick per cycle, and nothing changes
+            // until the last one
+            assert_eq!(bus.accesses.len(), cycles);
+            assert_eq!(bus.ticks, cycles);
+            assert_eq!(cpu.pc(), 0x0200);
+            assert_eq!(cpu.cycles(), cycles as u64);
+            cycles += 1;
+        }
+
+        assert_eq!(cycles, 6);
+        assert_eq!(bus.accesses.len(), 6);
+        assert_eq!(cpu.pc(), 0x0203);
+        assert_eq!(cpu.cycles(), 6);
+        assert_eq!(bus.ram.read(0x0080), 1);
+    }
+
+    #[test]
+    fn tick_matches_step() {
+        // LDX #$05, loop: DEC $80,X / DEX / BNE loop, JSR $0300, then a jam
+        let code = [0xA2, 0x05, 0xD6, 0x80, 0xCA, 0xD0, 0xFB, 0x20, 0x00, 0x03];
+        let (mut stepped, mut stepped_bus) = cycle_program(&code);
+        let (mut ticked, mut ticked_bus) = cycle_program(&code);
+        stepped_bus.ram.write(0x0300, 0x02);
+        ticked_bus.ram.write(0x0300, 0x02);
+
+        for _ in 0..20 {
+            stepped.step(&mut stepped_bus);
+
+            while !ticked.tick(&mut ticked_bus) {}
+
+            assert_eq!(ticked.pc(), stepped.pc());
+            assert_eq!(ticked.cycles(), stepped.cycles());
+            assert_eq!(ticked_bus.accesses, stepped_bus.accesses);
+        }
+
+        assert!(ticked.is_jammed());
+        assert_eq!(ticked_bus.ticks, stepped_bus.ticks);
+    }
+
+    #[test]
+    fn step_finishes_ticked_instruction() {
+        let (mut cpu, mut bus) = cycle_program(&[0xEE, 0x80, 0x00]); // INC $0080
+
+        cpu.tick(&mut bus);
+        cpu.tick(&mut bus);
+
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.pc(), 0x0203);
+        assert_eq!(bus.accesses.len(), 6);
+    }
+
+    // Holds RDY low for three cycles after any write to $0002, the way
+    // WSYNC does until the end of the line
+    struct RdyBus {
+        bus: CycleRecordingBus,
+        halt: u8,
+    }
+
+    impl Bus for RdyBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.bus.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            if address == 0x0002 {
+                self.halt = 3;
+            }
+            self.bus.write(address, data);
+        }
+
+        fn tick(&mut self, cycles: u8) {
+            self.halt = self.halt.saturating_sub(cycles);
+            self.bus.tick(cycles);
+        }
+
+        fn rdy(&self) -> bool {
+            self.halt == 0
+        }
+    }
+
+    #[test]
+    fn rdy_stalls_reads() {
+        let (mut cpu, bus) = cycle_program(&[0xE6, 0x02, 0xEA]); // INC $02, NOP
+        let mut bus = RdyBus { bus, halt: 0 };
+
+        // the dummy write pulls RDY low, but the write after it goes ahead
+        for _ in 0..4 {
+            assert!(!cpu.tick(&mut bus));
+        }
+        assert!(cpu.tick(&mut bus));
+
+        // then the opcode fetch repeats until RDY comes back
+        for _ in 0..3 {
+            assert!(!cpu.tick(&mut bus));
+        }
+        assert!(cpu.tick(&mut bus));
+
+        assert_eq!(cpu.cycles(), 9);
+        assert_eq!(cpu.pc(), 0x0203);
+        assert_eq!(bus.bus.ticks, 9);
+        assert_eq!(&bus.bus.accesses[4..], &[(0x0002, true), (0x0202, false), (0x0202, false),
+                                             (0x0202, false), (0x0203, false)]);
+    }
+
+    #[test]
+    fn rdy_stalls_step() {
+        let (mut cpu, bus) = cycle_program(&[0xEA]); // NOP
+        let mut bus = RdyBus { bus, halt: 2 };
+
+        assert_eq!(cpu.step(&mut bus), 4);
+        assert_eq!(cpu.cycles(), 4);
+        assert_eq!(bus.bus.accesses, vec![(0x0200, false), (0x0200, false),
+                                          (0x0200, false), (0x0201, false)]);
+    }
+
+    #[test]
+    fn reset() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(0xFFFC, &[0x00, 0xF0]);
+
+        cpu.reset(&mut bus);
+
+        assert_eq!(cpu.pc, 0xF000);
+        assert_eq!(cpu.sp, 0xFD);
+        assert!(cpu.flag_set(super::INTERRUPT_DISABLE_MASK));
+        assert_eq!(cpu.cycles(), 7);
+    }
+
+    #[test]
+    fn reset_leaves_stack_untouched() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(0x01FE, &[0x11, 0x22]);
+        bus.write(0x0100, 0x33);
+
+        cpu.reset(&mut bus);
+
+        assert_eq!(bus.read(0x01FE), 0x11);
+        assert_eq!(bus.read(0x01FF), 0x22);
+        assert_eq!(bus.read(0x0100), 0x33);
+    }
+
+    #[test]
+    fn reset_warm() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+        bus.load(0xFFFC, &[0x00, 0xF0]);
+        cpu.sp = 0x80;
+        cpu.a = 0x42;
+
+        cpu.reset(&mut bus);
+
+        // a warm reset still drops SP by three and keeps the registers
+        assert_eq!(cpu.sp, 0x7D);
+        assert_eq!(cpu.a, 0x42);
+    }
+
+    #[test]
+    fn reset_unjams() {
+        let mut cpu = Mos6507::new();
+        let mut bus = FlatRam::new();
+
+        cpu.kil();
+        cpu.reset(&mut bus);
+
+        assert!(!cpu.is_jammed());
+    }
+
+    #[test]
+    fn reset_vector_in_cartridge() {
+        let mut rom = vec![0; 0x1000];
+        rom[0x0FFC] = 0x34;
+        rom[0x0FFD] = 0xF2;
+        let mut bus = AtariBus::new(Cartridge::new(rom).unwrap());
+        let mut cpu = Mos6507::new();
+
+        cpu.reset(&mut bus);
+
+        assert_eq!(cpu.pc, 0xF234);
+    }
+
+    #[test]
+    fn brk_rti_round_trip() {
+        let (mut cpu, mut bus) = program(0x0200, &[0x00, 0xFF, 0xEA]); // BRK, padding, NOP
+        bus.load(0xFFFE, &[0x00, 0x30]);
+        bus.write(0x3000, 0x40); // RTI
+        cpu.sp = 0xFF;
+        cpu.flags = super::CARRY_MASK;
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 7);
+
+        // B and the unused bit only exist in the pushed copy
+        assert_eq!(cpu.pc, 0x3000);
+        assert!(!cpu.flag_set(super::BREAK_COMMAND_MASK));
+        assert_eq!(bus.read(0x01FD), super::CARRY_MASK | super::BREAK_COMMAND_MASK | super::UNUSED_MASK);
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 6);
+
+        assert_eq!(cpu.pc, 0x0202);
+        assert_eq!(cpu.sp, 0xFF);
+        assert_eq!(cpu.flags, super::CARRY_MASK);
+    }
+
+    // Drives the IRQ and NMI lines, optionally pulling NMI low after a
+    // number of writes so it lands in the middle of an instruction
+    #[cfg(feature = "interrupts")]
+    struct InterruptBus {
+        ram: FlatRam,
+        irq: bool,
+        nmi: bool,
+        nmi_after_writes: Option<usize>,
+    }
+
+    #[cfg(feature = "interrupts")]
+    impl Bus for InterruptBus {
+        fn read(&mut self, address: u16) -> u8 {
+            self.ram.read(address)
+        }
+
+        fn write(&mut self, address: u16, data: u8) {
+            self.ram.write(address, data);
+
+            if let Some(writes) = self.nmi_after_writes {
+                if writes <= 1 {
+                    self.nmi = true;
+                    self.nmi_after_writes = None;
+                } else {
+                    self.nmi_after_writes = Some(writes - 1);
+                }
+            }
+        }
+
+        fn irq(&self) -> bool {
+            self.irq
+        }
+
+        fn nmi(&self) -> bool {
+            self.nmi
+        }
+    }
+
+    #[cfg(feature = "interrupts")]
+    fn interrupt_program(code: &[u8]) -> (Mos6507, InterruptBus) {
+        let (mut cpu, mut ram) = program(0x0200, code);
+        ram.load(0xFFFA, &[0x00, 0x40, 0x00, 0x20, 0x00, 0x30]);
+        cpu.sp = 0xFF;
+        let bus = InterruptBus { ram, irq: false, nmi: false, nmi_after_writes: None };
+        (cpu, bus)
+    }
+
+    #[cfg(feature = "interrupts")]
+    #[test]
+    fn irq() {
+        let (mut cpu, mut bus) = interrupt_program(&[0xEA]); // NOP
+        cpu.flags = super::CARRY_MASK;
+        bus.irq = true;
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 7);
+
+        // the return address is the interrupted instruction, not past it
+        assert_eq!(cpu.pc, 0x3000);
+        assert_eq!(bus.ram.read(0x01FF), 0x02);
+        assert_eq!(bus.ram.read(0x01FE), 0x00);
+        assert_eq!(bus.ram.read(0x01FD), super::CARRY_MASK | super::UNUSED_MASK);
+        assert!(cpu.flag_set(super::INTERRUPT_DISABLE_MASK));
+    }
+
+    #[cfg(feature = "interrupts")]
+    #[test]
+    fn irq_masked() {
+        let (mut cpu, mut bus) = interrupt_program(&[0xEA]); // NOP
+        cpu.flags = super::INTERRUPT_DISABLE_MASK;
+        cpu.irq_inhibit = true;
+        bus.irq = true;
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 2);
+        assert_eq!(cpu.pc, 0x0201);
+    }
+
+    #[cfg(feature = "interrupts")]
+    #[test]
+    fn irq_delayed_after_cli() {
+        let (mut cpu, mut bus) = interrupt_program(&[0x58, 0xEA]); // CLI, NOP
+        cpu.flags = super::INTERRUPT_DISABLE_MASK;
+        cpu.irq_inhibit = true;
+        bus.irq = true;
+
+        cpu.execute_instruction(&mut bus);
+
+        // the instruction after CLI always runs before the IRQ is taken
+        assert_eq!(cpu.execute_instruction(&mut bus), 2);
+        assert_eq!(cpu.pc, 0x0202);
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.pc, 0x3000);
+    }
+
+    #[cfg(feature = "interrupts")]
+    #[test]
+    fn irq_taken_after_sei() {
+        let (mut cpu, mut bus) = interrupt_program(&[0x78, 0xEA]); // SEI, NOP
+        cpu.execute_instruction(&mut bus);
+        bus.irq = true;
+        cpu.execute_instruction(&mut bus);
+
+        // SEI lets one last IRQ through, though I is set in the pushed flags
+        assert_eq!(cpu.pc, 0x3000);
+        assert_eq!(bus.ram.read(0x01FD) & super::INTERRUPT_DISABLE_MASK, super::INTERRUPT_DISABLE_MASK);
+    }
+
+    #[cfg(feature = "interrupts")]
+    #[test]
+    fn nmi_edge_triggered() {
+        let (mut cpu, mut bus) = interrupt_program(&[0xEA, 0xEA]); // NOP, NOP
+        cpu.flags = super::INTERRUPT_DISABLE_MASK;
+        bus.nmi = true;
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 7);
+        assert_eq!(cpu.pc, 0x4000);
+
+        // holding the line low doesn't trigger another one
+        cpu.pc = 0x0200;
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 2);
+    }
+
+    #[cfg(feature = "interrupts")]
+    #[test]
+    fn nmi_hijacks_brk() {
+        let (mut cpu, mut bus) = interrupt_program(&[0x00, 0xFF]); // BRK
+        bus.nmi_after_writes = Some(2);
+
+        cpu.execute_instruction(&mut bus);
+
+        // the NMI handler runs, but the stack frame is still BRK's
+        assert_eq!(cpu.pc, 0x4000);
+        assert_eq!(bus.ram.read(0x01FE), 0x02);
+        assert_eq!(bus.ram.read(0x01FD) & super::BREAK_COMMAND_MASK, super::BREAK_COMMAND_MASK);
+
+        // and the NMI isn't taken again afterwards
+        cpu.pc = 0x0200;
+        bus.ram.write(0x0200, 0xEA);
+
+        assert_eq!(cpu.execute_instruction(&mut bus), 2);
+    }
+
+    #[cfg(feature = "interrupts")]
+    #[test]
+    fn nmi_hijacks_irq() {
+        let (mut cpu, mut bus) = interrupt_program(&[0xEA]); // NOP
+        bus.irq = true;
+        bus.nmi_after_writes = Some(3);
+
+        cpu.execute_instruction(&mut bus);
+
+        assert_eq!(cpu.pc, 0x4000);
+        assert_eq!(bus.ram.read(0x01FD) & super::BREAK_COMMAND_MASK, 0);
+    }
+
+    // Every A, operand and carry in combination, 256x256x2 in all
+    fn decimal_inputs() -> Vec<(u8, u8, u8)> {
+        let mut inputs = Vec::new();
+
+        for a in 0..256u16 {
+            for operand in 0..256u16 {
+                for carry in 0..2 {
+                    inputs.push((a as u8, operand as u8, carry));
+                }
+            }
+        }
+
+        inputs
+    }
+
+    // NMOS decimal ADC as worked out in Bruce Clark's "Decimal Mode"
+    // tutorial, which was checked against real hardware
+    fn reference_adc_decimal(a: u8, operand: u8, carry: u8) -> (u8, u8) {
+        let mut flags = super::DECIMAL_MASK;
+
+        let mut al = (a & 0x0F) as i32 + (operand & 0x0F) as i32 + carry as i32;
+        if al >= 0x0A {
+            al = ((al + 0x06) & 0x0F) + 0x10;
+        }
+
+        // N and V from the signed sum of the high digits
+        let signed = (a & 0xF0) as i8 as i32 + (operand & 0xF0) as i8 as i32 + al;
+        if signed & 0x80 != 0 {
+            flags |= super::NEGATIVE_MASK;
+        }
+        if !(-128..=127).contains(&signed) {
+            flags |= super::OVERFLOW_MASK;
+        }
+
+        let mut sum = (a & 0xF0) as i32 + (operand & 0xF0) as i32 + al;
+        if sum >= 0xA0 {
+            sum += 0x60;
+        }
+        if sum >= 0x100 {
+            flags |= super::CARRY_MASK;
+        }
+
+        if (a as i32 + operand as i32 + carry as i32) & 0xFF == 0 {
+            flags |= super::ZERO_RESULT_MASK;
+        }
+
+        (sum as u8, flags)
+    }
+
+    // NMOS decimal SBC from the same tutorial; the flags are those of the
+    // binary subtraction
+    fn reference_sbc_decimal(a: u8, operand: u8, carry: u8) -> (u8, u8) {
+        let mut flags = super::DECIMAL_MASK;
+
+        let mut al = (a & 0x0F) as i32 - (operand & 0x0F) as i32 + carry as i32 - 1;
+        if al < 0 {
+            al = ((al - 0x06) & 0x0F) - 0x10;
+        }
+
+        let mut difference = (a & 0xF0) as i32 - (operand & 0xF0) as i32 + al;
+        if difference < 0 {
+            difference -= 0x60;
+        }
+
+        let binary = a as i32 - operand as i32 + carry as i32 - 1;
+        if binary >= 0 {
+            flags |= super::CARRY_MASK;
+        }
+        if binary & 0xFF == 0 {
+            flags |= super::ZERO_RESULT_MASK;
+        }
+        if binary & 0x80 != 0 {
+            flags |= super::NEGATIVE_MASK;
+        }
+        if (a ^ operand) & (a ^ binary as u8) & 0x80 != 0 {
+            flags |= super::OVERFLOW_MASK;
+        }
+
+        (difference as u8, flags)
+    }
+
+    type AluOperation = fn(&mut Mos6507, u8) -> u8;
+
+    // The flag-setting ALU operations, each returning the register or
+    // memory value that the reference model predicts. The combined
+    // read-modify-write opcodes return A, which depends on the modified
+    // value. ANC, ALR, ARR, SBX and the unstable opcodes have their own
+    // tests instead
+    fn alu_operations() -> Vec<(&'static str, AluOperation)> {
+        vec![
+            ("ADC", |cpu, operand| { cpu.adc(operand); cpu.a }),
+            ("SBC", |cpu, operand| { cpu.sbc(operand); cpu.a }),
+            ("AND", |cpu, operand| { cpu.and(operand); cpu.a }),
+            ("ORA", |cpu, operand| { cpu.ora(operand); cpu.a }),
+            ("EOR", |cpu, operand| { cpu.eor(operand); cpu.a }),
+            ("BIT", |cpu, operand| { cpu.bit(operand); cpu.a }),
+            ("CMP", |cpu, operand| { cpu.cmp(operand); cpu.a }),
+            ("CPX", |cpu, operand| { cpu.cpx(operand); cpu.x }),
+            ("CPY", |cpu, operand| { cpu.cpy(operand); cpu.y }),
+            ("INC", |cpu, _| { let value = cpu.a; cpu.inc(value) }),
+            ("INX", |cpu, _| { cpu.inx(); cpu.x }),
+            ("INY", |cpu, _| { cpu.iny(); cpu.y }),
+            ("DEC", |cpu, _| { let value = cpu.a; cpu.dec(value) }),
+            ("DEX", |cpu, _| { cpu.dex(); cpu.x }),
+            ("DEY", |cpu, _| { cpu.dey(); cpu.y }),
+            ("ASL", |cpu, _| { let value = cpu.a; cpu.asl(value) }),
+            ("LSR", |cpu, _| { let value = cpu.a; cpu.lsr(value) }),
+            ("ROL", |cpu, _| { let value = cpu.a; cpu.rol(value) }),
+            ("ROR", |cpu, _| { let value = cpu.a; cpu.ror(value) }),
+            ("SLO", |cpu, operand| { cpu.slo(operand); cpu.a }),
+            ("RLA", |cpu, operand| { cpu.rla(operand); cpu.a }),
+            ("SRE", |cpu, operand| { cpu.sre(operand); cpu.a }),
+            ("RRA", |cpu, operand| { cpu.rra(operand); cpu.a }),
+            ("DCP", |cpu, operand| { cpu.dcp(operand); cpu.a }),
+            ("ISB", |cpu, operand| { cpu.isb(operand); cpu.a }),
+        ]
+    }
+
+    // A model of the ALU in plain integer arithmetic, with decimal mode
+    // ADC and SBC from the NMOS models above
+    fn reference_alu(mnemonic: &str, value: u8, operand: u8, flags: u8) -> (u8, u8) {
+        // the combined opcodes modify the operand, then combine A with it
+        let combined = match mnemonic {
+            "SLO" => Some(("ASL", "ORA")),
+            "RLA" => Some(("ROL", "AND")),
+            "SRE" => Some(("LSR", "EOR")),
+            "RRA" => Some(("ROR", "ADC")),
+            "DCP" => Some(("DEC", "CMP")),
+            "ISB" => Some(("INC", "SBC")),
+            _     => None,
+        };
+        if let Some((modify, combine)) = combined {
+            let (modified, flags) = reference_alu(modify, operand, 0, flags);
+            return reference_alu(combine, value, modified, flags);
+        }
+
+        let carry_in = flags & super::CARRY_MASK;
+        let decimal = (flags & super::DECIMAL_MASK) != 0;
+        let interrupt_disable = flags & super::INTERRUPT_DISABLE_MASK;
+        match mnemonic {
+            "ADC" if decimal => {
+                let (result, flags_out) = reference_adc_decimal(value, operand, carry_in);
+                return (result, flags_out | interrupt_disable);
+            },
+            "SBC" if decimal => {
+                let (result, flags_out) = reference_sbc_decimal(value, operand, carry_in);
+                return (result, flags_out | interrupt_disable);
+            },
+            _                => (),
+        }
+
+        let signed = |x: u8| x as i8 as i32;
+        let carry = (flags & super::CARRY_MASK) as i32;
+        let (value_wide, operand_wide) = (value as i32, operand as i32);
+        let mut carry_out = carry != 0;
+        let mut overflow = (flags & super::OVERFLOW_MASK) != 0;
+
+        let result = match mnemonic {
+            "ADC"                 => {
+                let signed_result = signed(value) + signed(operand) + carry;
+                overflow = !(-128..=127).contains(&signed_result);
+                carry_out = value_wide + operand_wide + carry > 0xFF;
+                value_wide + operand_wide + carry
+            },
+            "SBC"                 => {
+                let signed_result = signed(value) - signed(operand) - (1 - carry);
+                overflow = !(-128..=127).contains(&signed_result);
+                carry_out = value_wide - operand_wide - (1 - carry) >= 0;
+                value_wide - operand_wide - (1 - carry)
+            },
+            "AND"                 => value_wide & operand_wide,
+            "ORA"                 => value_wide | operand_wide,
+            "EOR"                 => value_wide ^ operand_wide,
+            "BIT"                 => {
+                overflow = (operand & 0x40) != 0;
+                value_wide & operand_wide
+            },
+            "CMP" | "CPX" | "CPY" => {
+                carry_out = value_wide >= operand_wide;
+                value_wide - operand_wide
+            },
+            "INC" | "INX" | "INY" => value_wide + 1,
+            "DEC" | "DEX" | "DEY" => value_wide - 1,
+            "ASL"                 => {
+                carry_out = (value & 0x80) != 0;
+                value_wide << 1
+            },
+            "LSR"                 => {
+                carry_out = (value & 0x01) != 0;
+                value_wide >> 1
+            },
+            "ROL"                 => {
+                carry_out = (value & 0x80) != 0;
+                (value_wide << 1) | carry
+            },
+            "ROR"                 => {
+                carry_out = (value & 0x01) != 0;
+                (value_wide >> 1) | (carry << 7)
+            },
+            _                     => unreachable!(),
+        };
+
+        let register = match mnemonic {
+            "BIT" | "CMP" | "CPX" | "CPY" => value,
+            _                             => result as u8,
+        };
+
+        let negative = match mnemonic {
+            "BIT" => operand,
+            _     => result as u8,
+        };
+
+        let mut flags_out = flags & (super::INTERRUPT_DISABLE_MASK | super::DECIMAL_MASK);
+        if carry_out {
+            flags_out |= super::CARRY_MASK;
+        }
+        if overflow {
+            flags_out |= super::OVERFLOW_MASK;
+        }
+        if (result & 0xFF) == 0 {
+            flags_out |= super::ZERO_RESULT_MASK;
+        }
+        if (negative & 0x80) != 0 {
+            flags_out |= super::NEGATIVE_MASK;
+        }
+
+        (register, flags_out)
+    }
+
+    #[test]
+    fn alu_exhaustive() {
+        let flag_inputs = [0, super::CARRY_MASK, super::OVERFLOW_MASK,
+                           super::CARRY_MASK | super::OVERFLOW_MASK | super::INTERRUPT_DISABLE_MASK,
+                           super::DECIMAL_MASK, super::DECIMAL_MASK | super::CARRY_MASK];
+
+        for (mnemonic, operation) in alu_operations() {
+            for value in 0..256u16 {
+                for operand in 0..256u16 {
+                    for flags in flag_inputs.iter() {
+                        let (value, operand) = (value as u8, operand as u8);
+                        let mut cpu = Mos6507::new();
+                        cpu.a = value;
+                        cpu.x = value;
+                        cpu.y = value;
+                        cpu.flags = *flags;
+
+                        let result = operation(&mut cpu, operand);
+
+                        assert_eq!((result, cpu.flags), reference_alu(mnemonic, value, operand, *flags),
+                                   "{} {:02X} {:02X} flags {:02X}", mnemonic, value, operand, flags);
+                    }
+                }
+            }
+        }
+    }
+
     #[test]
     fn flag_value() {
         let mut cpu = Mos6507::new();
         cpu.flags = 0x01 | 0x02 | 0x04 | 0x08 |
                     0x10 | 0x40 | 0x80;
         
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), true);
-        assert_eq!(cpu.flag_set(super::INTERRUPT_DISABLE_MASK), true);
-        assert_eq!(cpu.flag_set(super::DECIMAL_MASK), true);
-        assert_eq!(cpu.flag_set(super::BREAK_COMMAND_MASK), true);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), true);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(cpu.flag_set(super::ZERO_RESULT_MASK));
+        assert!(cpu.flag_set(super::INTERRUPT_DISABLE_MASK));
+        assert!(cpu.flag_set(super::DECIMAL_MASK));
+        assert!(cpu.flag_set(super::BREAK_COMMAND_MASK));
+        assert!(cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
     }
    
     #[test]
@@ -1282,13 +4545,13 @@ mod tests {
         cpu.flags = 0x01 | 0x04 |
                     0x10 | 0x80;
         
-        assert_eq!(cpu.flag_set(super::CARRY_MASK), true);
-        assert_eq!(cpu.flag_set(super::ZERO_RESULT_MASK), false);
-        assert_eq!(cpu.flag_set(super::INTERRUPT_DISABLE_MASK), true);
-        assert_eq!(cpu.flag_set(super::DECIMAL_MASK), false);
-        assert_eq!(cpu.flag_set(super::BREAK_COMMAND_MASK), true);
-        assert_eq!(cpu.flag_set(super::OVERFLOW_MASK), false);
-        assert_eq!(cpu.flag_set(super::NEGATIVE_MASK), true);
+        assert!(cpu.flag_set(super::CARRY_MASK));
+        assert!(!cpu.flag_set(super::ZERO_RESULT_MASK));
+        assert!(cpu.flag_set(super::INTERRUPT_DISABLE_MASK));
+        assert!(!cpu.flag_set(super::DECIMAL_MASK));
+        assert!(cpu.flag_set(super::BREAK_COMMAND_MASK));
+        assert!(!cpu.flag_set(super::OVERFLOW_MASK));
+        assert!(cpu.flag_set(super::NEGATIVE_MASK));
     }
 
     #[test]
@@ -0,0 +1,773 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use bus::{self, AtariBus, Bus};
+use disasm::Instruction;
+use rewind::Rewind;
+use tia::Beam;
+use trace;
+use Atari2600;
+
+// how far back to look for an instruction boundary before PC
+const DISASM_LOOKBEHIND: u16    = 9;
+const DISASM_BEFORE_PC: usize   = 3;
+
+// continue, line and frame give up after this many instructions, about
+// 50 frames, since there's no way to interrupt them
+const CONTINUE_LIMIT: u64       = 1_000_000;
+
+// TIA registers that hold a value, rather than being strobes
+const TIA_REGISTERS: [(u16, &str); 35] = [
+    (0x00, "VSYNC"),  (0x01, "VBLANK"), (0x04, "NUSIZ0"), (0x05, "NUSIZ1"),
+    (0x06, "COLUP0"), (0x07, "COLUP1"), (0x08, "COLUPF"), (0x09, "COLUBK"),
+    (0x0A, "CTRLPF"), (0x0B, "REFP0"),  (0x0C, "REFP1"),  (0x0D, "PF0"),
+    (0x0E, "PF1"),    (0x0F, "PF2"),    (0x15, "AUDC0"),  (0x16, "AUDC1"),
+    (0x17, "AUDF0"),  (0x18, "AUDF1"),  (0x19, "AUDV0"),  (0x1A, "AUDV1"),
+    (0x1B, "GRP0"),   (0x1C, "GRP1"),   (0x1D, "ENAM0"),  (0x1E, "ENAM1"),
+    (0x1F, "ENABL"),  (0x20, "HMP0"),   (0x21, "HMP1"),   (0x22, "HMM0"),
+    (0x23, "HMM1"),   (0x24, "HMBL"),   (0x25, "VDELP0"), (0x26, "VDELP1"),
+    (0x27, "VDELBL"), (0x28, "RESMP0"), (0x29, "RESMP1"),
+];
+
+const PIA_REGISTERS: [(u16, &str); 6] = [
+    (0x0280, "SWCHA"), (0x0281, "SWACNT"), (0x0282, "SWCHB"),
+    (0x0283, "SWBCNT"), (0x0284, "INTIM"), (0x0285, "TIMINT"),
+];
+
+const HELP: &str = "\
+step [n]                   run n instructions (s)
+line                       run to the start of the next scanline
+frame                      run to the start of the next frame
+continue [n]               run until something breaks, for at most n instructions (c)
+back [n]                   rewind to the start of the nth frame back
+break <addr>               break when PC reaches addr (b)
+watch <r|w|rw> <addr>      break when addr is read and/or written
+cond <reg> <op> <value>    break when a register compares true, e.g. cond x == 0
+delete <addr>              remove breakpoints and watchpoints on addr
+clear                      remove every breakpoint, watchpoint and condition
+list                       show breakpoints, watchpoints and conditions
+mem <addr> [len]           dump memory without side effects (m)
+poke <addr> <value>        write to memory through the bus
+reg                        show the registers (r)
+set <reg> <value>          set a, x, y, sp, pc or p
+disasm [n]                 disassemble around PC (d)
+tia                        show the TIA registers and beam
+pia                        show the PIA registers
+reset                      run the CPU's reset sequence
+save <file>                save the machine's state
+load <file>                restore a saved state
+quit                       leave (q)
+Addresses and values are hex; counts are decimal.";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Register {
+    A,
+    X,
+    Y,
+    Sp,
+    Pc,
+    P,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub address: u16,
+    pub access: Access,
+}
+
+// The first access in an instruction that tripped a watchpoint
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    pub watchpoint: Watchpoint,
+    pub address: u16,
+    pub data: u8,
+    pub write: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Condition {
+    register: Register,
+    comparison: String,
+    value: u16,
+}
+
+// What to run until, besides hitting a breakpoint
+enum Until {
+    Instructions(u64),
+    Scanline(Beam),
+    Frame(Beam),
+    Break(u64),
+}
+
+// Passes accesses through to the 2600's bus, noting the first one that
+// trips a watchpoint
+struct WatchBus<'a> {
+    bus: &'a mut AtariBus,
+    watchpoints: &'a [Watchpoint],
+    hit: Option<WatchHit>,
+}
+
+impl<'a> WatchBus<'a> {
+    fn check(&mut self, address: u16, data: u8, write: bool) {
+        if self.hit.is_some() {
+            return;
+        }
+
+        for watchpoint in self.watchpoints {
+            let matches = match watchpoint.access {
+                Access::Read      => !write,
+                Access::Write     => write,
+                Access::ReadWrite => true,
+            };
+
+            if matches && bus::canonical_address(watchpoint.address, write) == bus::canonical_address(address, write) {
+                self.hit = Some(WatchHit { watchpoint: *watchpoint, address, data, write });
+                return;
+            }
+        }
+    }
+}
+
+impl<'a> Bus for WatchBus<'a> {
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.bus.read(address);
+        self.check(address, data, false);
+        data
+    }
+
+    // these only happen to fall on a watched address, so they don't count
+    fn dummy_read(&mut self, address: u16) -> u8 {
+        self.bus.dummy_read(address)
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.bus.write(address, data);
+        self.check(address, data, true);
+    }
+
+    fn peek(&self, address: u16) -> u8 {
+        self.bus.peek(address)
+    }
+
+    fn tick(&mut self, cycles: u8) {
+        self.bus.tick(cycles);
+    }
+
+    fn rdy(&self) -> bool {
+        self.bus.rdy()
+    }
+
+    fn beam(&self) -> Beam {
+        self.bus.beam()
+    }
+}
+
+// The state behind the --debug prompt. Each command line is run against
+// the machine and returns the text to show
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<Watchpoint>,
+    conditions: Vec<Condition>,
+    rewind: Rewind,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    // Keeps a snapshot at the start of each frame run through, for back
+    pub fn with_rewind(rewind: Rewind) -> Debugger {
+        Debugger { rewind, ..Debugger::default() }
+    }
+
+    pub fn execute(&mut self, atari: &mut Atari2600, line: &str) -> String {
+        let words = line.split_whitespace().collect::<Vec<&str>>();
+
+        match self.command(atari, &words) {
+            Ok(text) => text,
+            Err(error) => format!("error: {}", error),
+        }
+    }
+
+    fn command(&mut self, atari: &mut Atari2600, words: &[&str]) -> Result<String, String> {
+        let command = match words.first() {
+            Some(command) => *command,
+            None          => return Ok(String::new()),
+        };
+        let args = &words[1..];
+
+        match command {
+            "s" | "step"     => {
+                let count = match args.first() {
+                    Some(count) => count.parse::<u64>().map_err(|_| format!("bad count {}", count))?,
+                    None        => 1,
+                };
+                Ok(self.run(atari, Until::Instructions(count)))
+            },
+            "line"           => {
+                let beam = atari.bus().beam();
+                Ok(self.run(atari, Until::Scanline(beam)))
+            },
+            "frame"          => {
+                let beam = atari.bus().beam();
+                Ok(self.run(atari, Until::Frame(beam)))
+            },
+            "c" | "continue" => {
+                let limit = match args.first() {
+                    Some(limit) => limit.parse::<u64>().map_err(|_| format!("bad count {}", limit))?,
+                    None        => CONTINUE_LIMIT,
+                };
+                Ok(self.run(atari, Until::Break(limit)))
+            },
+            "back"           => {
+                let count = match args.first() {
+                    Some(count) => count.parse::<u32>().map_err(|_| format!("bad count {}", count))?,
+                    None        => 1,
+                };
+                let target = atari.bus().beam().frame.saturating_sub(count);
+
+                if self.rewind.is_empty() {
+                    return Err("nothing to rewind".to_string());
+                }
+                while atari.bus().beam().frame > target && self.rewind.step_back(atari) {}
+                Ok(status(atari))
+            },
+            "b" | "break"    => {
+                let address = parse_hex(arg(args, 0)?)?;
+                self.breakpoints.push(address);
+                Ok(format!("breakpoint at ${:04X}", address))
+            },
+            "watch"          => {
+                let access = match arg(args, 0)? {
+                    "r"  => Access::Read,
+                    "w"  => Access::Write,
+                    "rw" => Access::ReadWrite,
+                    other => return Err(format!("bad access {}, expected r, w or rw", other)),
+                };
+                let address = parse_hex(arg(args, 1)?)?;
+                self.watchpoints.push(Watchpoint { address, access });
+                Ok(format!("watchpoint on ${:04X}", address))
+            },
+            "cond"           => {
+                let register = parse_register(arg(args, 0)?)?;
+                let comparison = arg(args, 1)?;
+                if !["==", "!=", "<", ">", "<=", ">="].contains(&comparison) {
+                    return Err(format!("bad comparison {}", comparison));
+                }
+                let value = parse_hex(arg(args, 2)?)?;
+                self.conditions.push(Condition { register, comparison: comparison.to_string(), value });
+                Ok(format!("condition {}", args.join(" ")))
+            },
+            "delete"         => {
+                let address = parse_hex(arg(args, 0)?)?;
+                self.breakpoints.retain(|breakpoint| *breakpoint != address);
+                self.watchpoints.retain(|watchpoint| watchpoint.address != address);
+                Ok(String::new())
+            },
+            "clear"          => {
+                self.breakpoints.clear();
+                self.watchpoints.clear();
+                self.conditions.clear();
+                Ok(String::new())
+            },
+            "list"           => Ok(self.list()),
+            "m" | "mem"      => {
+                let address = parse_hex(arg(args, 0)?)?;
+                let length = match args.get(1) {
+                    Some(length) => length.parse::<u16>().map_err(|_| format!("bad length {}", length))?,
+                    None         => 64,
+                };
+                Ok(dump(atari.bus(), address, length))
+            },
+            "poke"           => {
+                let address = parse_hex(arg(args, 0)?)?;
+                let value = parse_hex(arg(args, 1)?)?;
+                atari.bus().poke(address, value as u8);
+                Ok(String::new())
+            },
+            "r" | "reg"      => Ok(status(atari)),
+            "set"            => {
+                let register = parse_register(arg(args, 0)?)?;
+                let value = parse_hex(arg(args, 1)?)?;
+                set_register(atari, register, value);
+                Ok(status(atari))
+            },
+            "d" | "disasm"   => {
+                let count = match args.first() {
+                    Some(count) => count.parse::<usize>().map_err(|_| format!("bad count {}", count))?,
+                    None        => 8,
+                };
+                Ok(disassemble(atari, count))
+            },
+            "tia"            => Ok(tia(atari)),
+            "pia"            => Ok(pia(atari)),
+            "reset"          => {
+                atari.reset();
+                self.rewind.clear();
+                Ok(status(atari))
+            },
+            "save"           => {
+                let path = arg(args, 0)?;
+                File::create(path).and_then(|file| atari.save_state(&mut BufWriter::new(file)))
+                                  .map_err(|error| format!("can't save {}: {}", path, error))?;
+                Ok(format!("saved {}", path))
+            },
+            "load"           => {
+                let path = arg(args, 0)?;
+                File::open(path).and_then(|file| atari.load_state(&mut BufReader::new(file)))
+                                .map_err(|error| format!("can't load {}: {}", path, error))?;
+                self.rewind.clear();
+                Ok(status(atari))
+            },
+            "h" | "help"     => Ok(HELP.to_string()),
+            _                => Err(format!("unknown command {}, try help", command)),
+        }
+    }
+
+    // Steps instructions until the goal is reached or something breaks,
+    // returning why it stopped and where
+    fn run(&mut self, atari: &mut Atari2600, until: Until) -> String {
+        let mut count = 0;
+        let mut frame = atari.bus().beam().frame;
+
+        loop {
+            let hit = step_watched(atari, &self.watchpoints).map(|hit| {
+                let verb = if hit.write { "write" } else { "read" };
+                format!("watchpoint: {} ${:02X} at ${:04X}", verb, hit.data, hit.address)
+            });
+            count += 1;
+
+            if atari.bus().beam().frame != frame {
+                frame = atari.bus().beam().frame;
+                self.rewind.push(atari);
+            }
+
+            let reason = hit.or_else(|| self.check_breaks(atari));
+            if let Some(reason) = reason {
+                return format!("{}\n{}", reason, status(atari));
+            }
+
+            let beam = atari.bus().beam();
+            let (done, limit) = match until {
+                Until::Instructions(total) => (count >= total, u64::MAX),
+                Until::Scanline(start)     => (beam.scanline != start.scanline || beam.frame != start.frame, CONTINUE_LIMIT),
+                Until::Frame(start)        => (beam.frame != start.frame, CONTINUE_LIMIT),
+                Until::Break(limit)        => (false, limit),
+            };
+
+            if done {
+                return status(atari);
+            }
+
+            if count >= limit {
+                return format!("stopped after {} instructions\n{}", count, status(atari));
+            }
+        }
+    }
+
+    fn check_breaks(&self, atari: &mut Atari2600) -> Option<String> {
+        let pc = atari.cpu().pc();
+
+        if self.breakpoints.contains(&pc) {
+            return Some(format!("breakpoint at ${:04X}", pc));
+        }
+
+        for condition in &self.conditions {
+            let value = register(atari, condition.register);
+            let met = match condition.comparison.as_str() {
+                "==" => value == condition.value,
+                "!=" => value != condition.value,
+                "<"  => value < condition.value,
+                ">"  => value > condition.value,
+                "<=" => value <= condition.value,
+                _    => value >= condition.value,
+            };
+
+            if met {
+                return Some(format!("condition {:?} {} ${:X}", condition.register, condition.comparison,
+                                    condition.value));
+            }
+        }
+
+        if atari.cpu().is_jammed() {
+            return Some("CPU jammed".to_string());
+        }
+
+        None
+    }
+
+    fn list(&self) -> String {
+        let mut lines = Vec::new();
+
+        for breakpoint in &self.breakpoints {
+            lines.push(format!("break ${:04X}", breakpoint));
+        }
+        for watchpoint in &self.watchpoints {
+            lines.push(format!("watch {:?} ${:04X}", watchpoint.access, watchpoint.address));
+        }
+        for condition in &self.conditions {
+            lines.push(format!("cond {:?} {} ${:X}", condition.register, condition.comparison, condition.value));
+        }
+
+        lines.join("\n")
+    }
+}
+
+// Runs one instruction, returning the first access it made that tripped
+// one of the watchpoints
+pub fn step_watched(atari: &mut Atari2600, watchpoints: &[Watchpoint]) -> Option<WatchHit> {
+    let (cpu, bus) = atari.parts();
+    let mut watch_bus = WatchBus {
+        bus,
+        watchpoints,
+        hit: None,
+    };
+
+    cpu.step(&mut watch_bus);
+    watch_bus.hit
+}
+
+fn arg<'a>(args: &[&'a str], index: usize) -> Result<&'a str, String> {
+    args.get(index).cloned().ok_or_else(|| "missing argument, try help".to_string())
+}
+
+// Parses hex, with or without a leading $ or 0x
+fn parse_hex(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("bad number {}", text))
+}
+
+fn parse_register(text: &str) -> Result<Register, String> {
+    match text.to_lowercase().as_str() {
+        "a"  => Ok(Register::A),
+        "x"  => Ok(Register::X),
+        "y"  => Ok(Register::Y),
+        "sp" => Ok(Register::Sp),
+        "pc" => Ok(Register::Pc),
+        "p"  => Ok(Register::P),
+        _    => Err(format!("bad register {}", text)),
+    }
+}
+
+fn register(atari: &Atari2600, register: Register) -> u16 {
+    let cpu = atari.cpu();
+
+    match register {
+        Register::A  => cpu.a() as u16,
+        Register::X  => cpu.x() as u16,
+        Register::Y  => cpu.y() as u16,
+        Register::Sp => cpu.sp() as u16,
+        Register::Pc => cpu.pc(),
+        Register::P  => cpu.flags() as u16,
+    }
+}
+
+fn set_register(atari: &mut Atari2600, register: Register, value: u16) {
+    let (cpu, _) = atari.parts();
+
+    match register {
+        Register::A  => cpu.set_a(value as u8),
+        Register::X  => cpu.set_x(value as u8),
+        Register::Y  => cpu.set_y(value as u8),
+        Register::Sp => cpu.set_sp(value as u8),
+        Register::Pc => cpu.set_pc(value),
+        Register::P  => cpu.set_flags(value as u8),
+    }
+}
+
+// The next instruction and the registers, as a trace line
+fn status(atari: &mut Atari2600) -> String {
+    let (cpu, bus) = atari.parts();
+    trace::line(cpu, bus)
+}
+
+fn dump(bus: &AtariBus, address: u16, length: u16) -> String {
+    let mut lines = Vec::new();
+
+    for offset in (0..length).step_by(16) {
+        let start = address.wrapping_add(offset);
+        let bytes = (0..16.min(length - offset))
+            .map(|column| format!("{:02X}", bus.peek(start.wrapping_add(column))))
+            .collect::<Vec<String>>();
+
+        lines.push(format!("{:04X}  {}", start, bytes.join(" ")));
+    }
+
+    lines.join("\n")
+}
+
+// Lists a few instructions before PC and count from it. Going backwards
+// is a guess: of the starting points that decode to land exactly on PC,
+// take the one with the fewest illegal opcodes just before it
+fn disassemble(atari: &mut Atari2600, count: usize) -> String {
+    let pc = atari.cpu().pc();
+    let bus = atari.bus();
+
+    let decode = |address: u16| {
+        let bytes = [bus.peek(address), bus.peek(address.wrapping_add(1)), bus.peek(address.wrapping_add(2))];
+        Instruction::decode(&bytes, address).unwrap()
+    };
+
+    let mut before: Vec<Instruction> = Vec::new();
+    let mut fewest_illegal = usize::MAX;
+
+    for lookbehind in (1..=DISASM_LOOKBEHIND).rev() {
+        let mut address = pc.wrapping_sub(lookbehind);
+        let mut instructions = Vec::new();
+
+        while address.wrapping_sub(pc.wrapping_sub(lookbehind)) < lookbehind {
+            let instruction = decode(address);
+            address = instruction.next();
+            instructions.push(instruction);
+        }
+
+        let skip = instructions.len().saturating_sub(DISASM_BEFORE_PC);
+        instructions.drain(..skip);
+        let illegal = instructions.iter().filter(|instruction| instruction.illegal).count();
+
+        if address == pc && illegal < fewest_illegal {
+            before = instructions;
+            fewest_illegal = illegal;
+        }
+    }
+
+    let mut lines = before.iter().map(|instruction| format!("  {}", instruction.listing()))
+                                 .collect::<Vec<String>>();
+
+    let mut address = pc;
+    for i in 0..count {
+        let instruction = decode(address);
+        let marker = if i == 0 { ">" } else { " " };
+
+        lines.push(format!("{} {}", marker, instruction.listing()));
+        address = instruction.next();
+    }
+
+    lines.join("\n")
+}
+
+fn tia(atari: &mut Atari2600) -> String {
+    let tia = atari.bus().tia();
+    let beam = tia.beam();
+
+    let mut lines = TIA_REGISTERS.chunks(4).map(|row| {
+        row.iter().map(|&(address, name)| format!("{:<6} ${:02X}", name, tia.register(address)))
+                  .collect::<Vec<String>>()
+                  .join("  ")
+    }).collect::<Vec<String>>();
+
+    let positions = tia.positions();
+    lines.push(format!("P0 {} P1 {} M0 {} M1 {} BL {}", positions[0], positions[1], positions[2], positions[3],
+                       positions[4]));
+    lines.push(format!("frame {} scanline {} color clock {}", beam.frame, beam.scanline, beam.color_clock));
+    lines.join("\n")
+}
+
+fn pia(atari: &mut Atari2600) -> String {
+    let pia = atari.bus().pia();
+
+    PIA_REGISTERS.iter().map(|&(address, name)| format!("{:<6} ${:02X}", name, pia.peek(address)))
+                        .collect::<Vec<String>>()
+                        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+    use test_atari;
+
+    // LDX #$03, loop: STX $80 / DEX / BNE loop, then NOPs
+    fn debug_atari() -> Atari2600 {
+        test_atari(&[0xA2, 0x03, 0x86, 0x80, 0xCA, 0xD0, 0xFB])
+    }
+
+    #[test]
+    fn step() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        let text = debugger.execute(&mut atari, "step 2");
+
+        assert!(text.starts_with("F004  CA        DEX"));
+        assert_eq!(atari.cpu().pc(), 0xF004);
+    }
+
+    #[test]
+    fn breakpoint() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        debugger.execute(&mut atari, "break F007");
+        let text = debugger.execute(&mut atari, "c");
+
+        assert!(text.starts_with("breakpoint at $F007"));
+        assert_eq!(atari.cpu().x(), 0);
+    }
+
+    #[test]
+    fn watchpoint() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        debugger.execute(&mut atari, "watch w 80");
+        let text = debugger.execute(&mut atari, "continue");
+
+        assert!(text.starts_with("watchpoint: write $03 at $0080"));
+        assert_eq!(atari.cpu().pc(), 0xF004);
+    }
+
+    #[test]
+    fn watchpoint_mirrors() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        // $0180 is the same RAM byte as $80
+        debugger.execute(&mut atari, "watch rw 180");
+        let text = debugger.execute(&mut atari, "c");
+
+        assert!(text.starts_with("watchpoint: write $03 at $0080"));
+    }
+
+    #[test]
+    fn watchpoint_skips_dummy_reads() {
+        // LDX #$03 / LDA $80,X, which reads $80 before adding X
+        let mut atari = test_atari(&[0xA2, 0x03, 0xB5, 0x80]);
+        let mut debugger = Debugger::new();
+
+        debugger.execute(&mut atari, "watch r 80");
+        debugger.execute(&mut atari, "watch r 83");
+        let text = debugger.execute(&mut atari, "c");
+
+        assert!(text.starts_with("watchpoint: read $00 at $0083"));
+    }
+
+    #[test]
+    fn continue_limit() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        let text = debugger.execute(&mut atari, "c 5");
+
+        assert!(text.starts_with("stopped after 5 instructions"));
+        assert_eq!(atari.cpu().pc(), 0xF004);
+    }
+
+    #[test]
+    fn frame_limit() {
+        // loop: JMP loop, which never starts a new frame with VSYNC
+        let mut atari = test_atari(&[0x4C, 0x00, 0xF0]);
+        let mut debugger = Debugger::new();
+
+        let text = debugger.execute(&mut atari, "frame");
+
+        assert!(text.starts_with("stopped after 1000000 instructions"));
+        assert_eq!(atari.cpu().pc(), 0xF000);
+    }
+
+    #[test]
+    fn condition() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        debugger.execute(&mut atari, "cond x < 2");
+        debugger.execute(&mut atari, "c");
+
+        assert_eq!(atari.cpu().x(), 1);
+        assert_eq!(atari.cpu().pc(), 0xF005);
+    }
+
+    #[test]
+    fn memory_and_registers() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        let beam = atari.bus().beam();
+        debugger.execute(&mut atari, "poke 81 42");
+        debugger.execute(&mut atari, "set a $24");
+
+        assert_eq!(debugger.execute(&mut atari, "mem 80 4"), "0080  00 42 00 00");
+        assert_eq!(atari.bus().beam(), beam);
+        assert_eq!(atari.cpu().a(), 0x24);
+        assert_eq!(debugger.execute(&mut atari, "set q 0"), "error: bad register q");
+    }
+
+    #[test]
+    fn dump_whole_address_space() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        let text = debugger.execute(&mut atari, "mem 0 65535");
+        let lines = text.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines.len(), 4096);
+        assert_eq!(lines[4095], "FFF0  EA EA EA EA EA EA EA EA EA EA EA EA 00 F0 EA");
+    }
+
+    #[test]
+    fn disassemble_around_pc() {
+        let mut atari = debug_atari();
+        let mut debugger = Debugger::new();
+
+        debugger.execute(&mut atari, "step 3");
+        let text = debugger.execute(&mut atari, "disasm 2");
+
+        assert_eq!(text, "  F000  A2 03     LDX #$03\n\
+                          \x20 F002  86 80     STX $80\n\
+                          \x20 F004  CA        DEX\n\
+                          > F005  D0 FB     BNE $F002\n\
+                          \x20 F007  EA        NOP");
+    }
+
+    // loop: VSYNC on and off, then INC $80
+    fn frame_atari() -> Atari2600 {
+        test_atari(&[0xA9, 0x02, 0x85, 0x00, 0xA9, 0x00, 0x85, 0x00, 0xE6, 0x80, 0x4C, 0x00, 0xF0])
+    }
+
+    #[test]
+    fn back() {
+        let mut atari = frame_atari();
+        let mut debugger = Debugger::new();
+
+        assert_eq!(debugger.execute(&mut atari, "back"), "error: nothing to rewind");
+
+        for _ in 0..3 {
+            debugger.execute(&mut atari, "frame");
+        }
+        assert_eq!(atari.bus().peek(0x0080), 2);
+
+        debugger.execute(&mut atari, "back 2");
+
+        assert_eq!(atari.bus().beam().frame, 1);
+        assert_eq!(atari.bus().peek(0x0080), 0);
+        assert_eq!(atari.cpu().pc(), 0xF004);
+    }
+
+    #[test]
+    fn reset_and_load_drop_rewind() {
+        let mut atari = frame_atari();
+        let mut debugger = Debugger::new();
+        let path = env::temp_dir().join(format!("twenty_six_debugger_{}.state", process::id()));
+        let path = path.to_str().unwrap();
+
+        debugger.execute(&mut atari, &format!("save {}", path));
+        debugger.execute(&mut atari, "frame");
+        debugger.execute(&mut atari, &format!("load {}", path));
+        assert_eq!(debugger.execute(&mut atari, "back"), "error: nothing to rewind");
+
+        debugger.execute(&mut atari, "frame");
+        debugger.execute(&mut atari, "reset");
+        assert_eq!(debugger.execute(&mut atari, "back"), "error: nothing to rewind");
+
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+
+use cpu::{AddressMode, OPCODES};
+
+// the only documented NOP; every other one is illegal
+const NOP: u8                       = 0xEA;
+// an illegal duplicate of the documented SBC immediate
+const SBC_IMMEDIATE_ILLEGAL: u8     = 0xEB;
+
+// A single decoded instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub mnemonic: &'static str,
+    pub address_mode: AddressMode,
+    pub illegal: bool,
+}
+
+impl Instruction {
+    // Decodes the instruction at the start of the slice, or returns None
+    // when the slice ends before its operand does
+    pub fn decode(bytes: &[u8], address: u16) -> Option<Instruction> {
+        let opcode = *bytes.first()?;
+        let entry = &OPCODES[opcode as usize];
+        let length = entry.bytes as usize;
+
+        if bytes.len() < length {
+            return None;
+        }
+
+        Some(Instruction {
+            address,
+            bytes: bytes[..length].to_vec(),
+            mnemonic: entry.mnemonic,
+            address_mode: entry.address_mode,
+            illegal: is_illegal(opcode),
+        })
+    }
+
+    pub fn opcode(&self) -> u8 {
+        self.bytes[0]
+    }
+
+    // The operand as a byte or little endian word, 0 if there isn't one
+    pub fn operand(&self) -> u16 {
+        match self.bytes.len() {
+            2 => self.bytes[1] as u16,
+            3 => ((self.bytes[2] as u16) << 8) | self.bytes[1] as u16,
+            _ => 0,
+        }
+    }
+
+    // Where a branch goes when it's taken
+    pub fn target(&self) -> Option<u16> {
+        match self.address_mode {
+            AddressMode::Relative => {
+                let offset = self.bytes[1] as i8 as u16;
+                Some(self.next().wrapping_add(offset))
+            },
+            _                     => None,
+        }
+    }
+
+    // The address of the following instruction
+    pub fn next(&self) -> u16 {
+        self.address.wrapping_add(self.bytes.len() as u16)
+    }
+
+    // Formats a listing line of address, raw bytes and the instruction,
+    // with illegal opcodes marked by a * as nestest and Stella do
+    pub fn listing(&self) -> String {
+        let bytes = self.bytes.iter()
+                              .map(|byte| format!("{:02X}", byte))
+                              .collect::<Vec<String>>()
+                              .join(" ");
+        let marker = if self.illegal { "*" } else { " " };
+
+        format!("{:04X}  {:<8} {}{}", self.address, bytes, marker, self)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operand = self.operand();
+
+        match self.address_mode {
+            AddressMode::Immediate   => write!(f, "{} #${:02X}", self.mnemonic, operand),
+            AddressMode::ZeroPage    => write!(f, "{} ${:02X}", self.mnemonic, operand),
+            AddressMode::ZeroPageX   => write!(f, "{} ${:02X},X", self.mnemonic, operand),
+            AddressMode::ZeroPageY   => write!(f, "{} ${:02X},Y", self.mnemonic, operand),
+            AddressMode::Absolute    => write!(f, "{} ${:04X}", self.mnemonic, operand),
+            AddressMode::AbsoluteX   => write!(f, "{} ${:04X},X", self.mnemonic, operand),
+            AddressMode::AbsoluteY   => write!(f, "{} ${:04X},Y", self.mnemonic, operand),
+            AddressMode::Indirect    => write!(f, "{} (${:04X})", self.mnemonic, operand),
+            AddressMode::IndirectX   => write!(f, "{} (${:02X},X)", self.mnemonic, operand),
+            AddressMode::IndirectY   => write!(f, "{} (${:02X}),Y", self.mnemonic, operand),
+            AddressMode::Relative    => write!(f, "{} ${:04X}", self.mnemonic, self.target().unwrap()),
+            AddressMode::Accumulator => write!(f, "{} A", self.mnemonic),
+            AddressMode::None        => write!(f, "{}", self.mnemonic),
+        }
+    }
+}
+
+// Decodes instructions from the slice, which starts at the given address,
+// up to the last one that fits entirely
+pub fn disassemble(bytes: &[u8], address: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut offset = 0;
+
+    while let Some(instruction) = Instruction::decode(&bytes[offset..], address.wrapping_add(offset as u16)) {
+        offset += instruction.bytes.len();
+        instructions.push(instruction);
+    }
+
+    instructions
+}
+
+// Disassembles the slice as listing lines, with any bytes left over at the
+// end that don't make a whole instruction shown as data
+pub fn listing(bytes: &[u8], address: u16) -> String {
+    let mut text = String::new();
+    let mut offset = 0;
+
+    for instruction in disassemble(bytes, address) {
+        offset += instruction.bytes.len();
+        text.push_str(&instruction.listing());
+        text.push('\n');
+    }
+
+    for (i, byte) in bytes[offset..].iter().enumerate() {
+        let byte_address = address.wrapping_add((offset + i) as u16);
+        text.push_str(&format!("{:04X}  {:02X}        .byte ${:02X}\n", byte_address, byte, byte));
+    }
+
+    text
+}
+
+// Whether an opcode is one of the undocumented ones
+pub fn is_illegal(opcode: u8) -> bool {
+    match OPCODES[opcode as usize].mnemonic {
+        "NOP" => opcode != NOP,
+        "SBC" => opcode == SBC_IMMEDIATE_ILLEGAL,
+        "KIL" | "SLO" | "RLA" | "SRE" | "RRA" | "SAX" | "LAX" | "DCP" | "ISB" |
+        "ANC" | "ALR" | "ARR" | "ANE" | "LXA" | "SBX" | "SHA" | "SHX" | "SHY" |
+        "TAS" | "LAS" => true,
+        _     => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn address_modes() {
+        let cases: [(&[u8], &str); 13] = [
+            (&[0xA9, 0x42],       "LDA #$42"),
+            (&[0xA5, 0x80],       "LDA $80"),
+            (&[0xB5, 0x80],       "LDA $80,X"),
+            (&[0xB6, 0x80],       "LDX $80,Y"),
+            (&[0xAD, 0x34, 0x12], "LDA $1234"),
+            (&[0xBD, 0x34, 0x12], "LDA $1234,X"),
+            (&[0xB9, 0x34, 0x12], "LDA $1234,Y"),
+            (&[0x6C, 0xFC, 0xFF], "JMP ($FFFC)"),
+            (&[0xA1, 0x80],       "LDA ($80,X)"),
+            (&[0xB1, 0x80],       "LDA ($80),Y"),
+            (&[0xD0, 0xFE],       "BNE $F000"),
+            (&[0x0A],             "ASL A"),
+            (&[0xEA],             "NOP"),
+        ];
+
+        for &(bytes, text) in cases.iter() {
+            assert_eq!(Instruction::decode(bytes, 0xF000).unwrap().to_string(), text);
+        }
+    }
+
+    #[test]
+    fn branch_targets() {
+        let forward = Instruction::decode(&[0x10, 0x10], 0xF0F0).unwrap();
+        let backward = Instruction::decode(&[0x30, 0x80], 0xF000).unwrap();
+
+        assert_eq!(forward.target(), Some(0xF102));
+        assert_eq!(backward.target(), Some(0xEF82));
+        assert_eq!(Instruction::decode(&[0xEA], 0xF000).unwrap().target(), None);
+    }
+
+    #[test]
+    fn illegal_opcodes() {
+        let slo = Instruction::decode(&[0x07, 0x80], 0xF000).unwrap();
+
+        assert!(slo.illegal);
+        assert_eq!(slo.listing(), "F000  07 80    *SLO $80");
+        assert!(is_illegal(0x1A));
+        assert!(is_illegal(0xEB));
+        assert!(!is_illegal(0xEA));
+        assert!(!is_illegal(0xE9));
+
+        // 151 documented opcodes
+        assert_eq!((0..256).filter(|&opcode| !is_illegal(opcode as u8)).count(), 151);
+    }
+
+    #[test]
+    fn listing_leftovers() {
+        let text = listing(&[0x4C, 0x00, 0xF0, 0xAD, 0x80], 0xF000);
+
+        assert_eq!(text, "F000  4C 00 F0  JMP $F000\n\
+                          F003  AD        .byte $AD\n\
+                          F004  80        .byte $80\n");
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(Instruction::decode(&[0xAD, 0x80], 0xF000), None);
+        assert_eq!(Instruction::decode(&[], 0xF000), None);
+        assert_eq!(disassemble(&[0xEA, 0xEA, 0xA9], 0xF000).len(), 2);
+    }
+}
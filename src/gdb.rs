@@ -0,0 +1,477 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+use bus::Bus;
+use debugger::{self, Access, Watchpoint};
+use Atari2600;
+
+// GDB has no 6502 of its own, so clients are told the register layout:
+// a, x, y, p and sp are a byte each, then pc is a little endian word
+const TARGET_XML: &str = "<?xml version=\"1.0\"?>\
+<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+<target version=\"1.0\">\
+<feature name=\"org.twentysix.mos6507\">\
+<reg name=\"a\" bitsize=\"8\" regnum=\"0\"/>\
+<reg name=\"x\" bitsize=\"8\"/>\
+<reg name=\"y\" bitsize=\"8\"/>\
+<reg name=\"p\" bitsize=\"8\"/>\
+<reg name=\"sp\" bitsize=\"8\"/>\
+<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>\
+</feature>\
+</target>";
+
+// stop signals
+const SIGINT: u8                = 2;
+const SIGILL: u8                = 4;
+const SIGTRAP: u8               = 5;
+
+// the client's interrupt request, sent outside of any packet
+const INTERRUPT: u8             = 0x03;
+
+// how many instructions to run between checks for an interrupt
+const INTERRUPT_POLL: u32       = 4096;
+
+const PACKET_SIZE: usize        = 0x1000;
+
+// A GDB remote serial protocol stub that drives an Atari2600 for one
+// client connection at a time
+#[derive(Default)]
+pub struct GdbServer {
+    breakpoints: Vec<u16>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl GdbServer {
+    pub fn new() -> GdbServer {
+        GdbServer::default()
+    }
+
+    // Answers packets until the client detaches, kills the session or
+    // drops the connection
+    pub fn serve(&mut self, atari: &mut Atari2600, mut stream: TcpStream) -> io::Result<()> {
+        // packets and acks are tiny, so don't let them sit waiting
+        stream.set_nodelay(true)?;
+
+        while let Some(packet) = read_packet(&mut stream)? {
+            match self.handle(atari, &packet, &mut stream)? {
+                Some(reply) => write_packet(&mut stream, &reply)?,
+                None        => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the reply to a packet, or None to end the session
+    fn handle(&mut self, atari: &mut Atari2600, packet: &str, stream: &mut TcpStream) -> io::Result<Option<String>> {
+        // the command is one character, which needn't be a single byte
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        let reply = match command {
+            "?" => stop_reply(SIGTRAP),
+            "g" => {
+                let cpu = atari.cpu();
+                let pc = cpu.pc();
+                hex(&[cpu.a(), cpu.x(), cpu.y(), cpu.flags(), cpu.sp(), pc as u8, (pc >> 8) as u8])
+            },
+            "G" => match unhex(args) {
+                Some(ref bytes) if bytes.len() == 7 => {
+                    for (register, value) in [0, 1, 2, 3, 4].iter().zip(bytes) {
+                        set_register(atari, *register, *value as u16);
+                    }
+                    set_register(atari, 5, ((bytes[6] as u16) << 8) | bytes[5] as u16);
+                    "OK".to_string()
+                },
+                _ => error(),
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(register) if register < 5 => hex(&[register_bytes(atari)[register]]),
+                Ok(5)                        => hex(&register_bytes(atari)[5..]),
+                _                            => error(),
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                let register = parts.next().and_then(|register| usize::from_str_radix(register, 16).ok());
+                let value = parts.next().and_then(unhex);
+
+                match (register, value) {
+                    (Some(register), Some(ref value)) if register <= 5 && !value.is_empty() => {
+                        // values come over the wire little endian
+                        let word = value.iter().rev().fold(0u16, |word, byte| (word << 8) | *byte as u16);
+                        set_register(atari, register, word);
+                        "OK".to_string()
+                    },
+                    _ => error(),
+                }
+            },
+            "m" => match address_length(args) {
+                Some((address, length)) => {
+                    let bus = atari.bus();
+                    let bytes = (0..length).map(|offset| bus.peek(address.wrapping_add(offset)))
+                                           .collect::<Vec<u8>>();
+                    hex(&bytes)
+                },
+                None => error(),
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                let target = parts.next().and_then(address_length);
+                let data = parts.next().and_then(unhex);
+
+                match (target, data) {
+                    (Some((address, length)), Some(ref data)) if data.len() == length as usize => {
+                        for (offset, byte) in data.iter().enumerate() {
+                            atari.bus().poke(address.wrapping_add(offset as u16), *byte);
+                        }
+                        "OK".to_string()
+                    },
+                    _ => error(),
+                }
+            },
+            "Z" | "z" => match self.set_point(command == "Z", args) {
+                Some(()) => "OK".to_string(),
+                None     => String::new(),
+            },
+            "s" => {
+                let pc = resume_address(args);
+                self.resume(atari, pc, stream, true)?
+            },
+            "c" => {
+                let pc = resume_address(args);
+                self.resume(atari, pc, stream, false)?
+            },
+            "H" => "OK".to_string(),
+            "q" => query(args),
+            "D" => {
+                write_packet(stream, "OK")?;
+                return Ok(None);
+            },
+            "k" => return Ok(None),
+            _   => String::new(),
+        };
+
+        Ok(Some(reply))
+    }
+
+    // Inserts or removes a breakpoint (types 0 and 1) or a watchpoint
+    // (2 write, 3 read, 4 access). Returns None for anything unsupported
+    fn set_point(&mut self, insert: bool, args: &str) -> Option<()> {
+        let mut parts = args.split(',');
+        let kind = parts.next()?;
+        let address = u16::from_str_radix(parts.next()?, 16).ok()?;
+
+        let access = match kind {
+            "0" | "1" => {
+                self.breakpoints.retain(|breakpoint| *breakpoint != address);
+                if insert {
+                    self.breakpoints.push(address);
+                }
+                return Some(());
+            },
+            "2"       => Access::Write,
+            "3"       => Access::Read,
+            "4"       => Access::ReadWrite,
+            _         => return None,
+        };
+
+        let watchpoint = Watchpoint { address, access };
+        self.watchpoints.retain(|existing| *existing != watchpoint);
+        if insert {
+            self.watchpoints.push(watchpoint);
+        }
+
+        Some(())
+    }
+
+    // Runs one instruction, or until something stops the CPU, and returns
+    // the stop reply
+    fn resume(&self, atari: &mut Atari2600, pc: Option<u16>, stream: &mut TcpStream, step: bool) -> io::Result<String> {
+        if let Some(pc) = pc {
+            set_register(atari, 5, pc);
+        }
+
+        let mut count = 0u32;
+
+        loop {
+            if let Some(hit) = debugger::step_watched(atari, &self.watchpoints) {
+                let kind = match hit.watchpoint.access {
+                    Access::Write     => "watch",
+                    Access::Read      => "rwatch",
+                    Access::ReadWrite => "awatch",
+                };
+                return Ok(format!("T{:02x}{}:{:04x};", SIGTRAP, kind, hit.watchpoint.address));
+            }
+
+            if atari.cpu().is_jammed() {
+                return Ok(stop_reply(SIGILL));
+            }
+
+            if step || self.breakpoints.contains(&atari.cpu().pc()) {
+                return Ok(stop_reply(SIGTRAP));
+            }
+
+            count += 1;
+            if count == INTERRUPT_POLL {
+                count = 0;
+                if interrupted(stream)? {
+                    return Ok(stop_reply(SIGINT));
+                }
+            }
+        }
+    }
+}
+
+fn query(args: &str) -> String {
+    if args.starts_with("Supported") {
+        format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE)
+    } else if args == "Attached" {
+        "1".to_string()
+    } else if args == "C" {
+        "QC1".to_string()
+    } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+        // annex reads come in pieces, each prefixed m if there's more
+        match address_length(range) {
+            Some((offset, length)) => {
+                let start = (offset as usize).min(TARGET_XML.len());
+                let end = (start + length as usize).min(TARGET_XML.len());
+                let marker = if end < TARGET_XML.len() { "m" } else { "l" };
+                format!("{}{}", marker, &TARGET_XML[start..end])
+            },
+            None => error(),
+        }
+    } else {
+        String::new()
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+fn register_bytes(atari: &Atari2600) -> [u8; 7] {
+    let cpu = atari.cpu();
+    let pc = cpu.pc();
+    [cpu.a(), cpu.x(), cpu.y(), cpu.flags(), cpu.sp(), pc as u8, (pc >> 8) as u8]
+}
+
+// Registers are numbered as in TARGET_XML
+fn set_register(atari: &mut Atari2600, register: usize, value: u16) {
+    let (cpu, _) = atari.parts();
+
+    match register {
+        0 => cpu.set_a(value as u8),
+        1 => cpu.set_x(value as u8),
+        2 => cpu.set_y(value as u8),
+        3 => cpu.set_flags(value as u8),
+        4 => cpu.set_sp(value as u8),
+        _ => cpu.set_pc(value),
+    }
+}
+
+// s and c can carry an address to resume from
+fn resume_address(args: &str) -> Option<u16> {
+    u16::from_str_radix(args, 16).ok()
+}
+
+// Parses the addr,length pairs used by m, M and qXfer
+fn address_length(args: &str) -> Option<(u16, u16)> {
+    let mut parts = args.splitn(2, ',');
+    let address = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let length = u32::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some((address as u16, length.min(PACKET_SIZE as u32 / 2) as u16))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.is_ascii() || text.len() % 2 == 1 {
+        return None;
+    }
+
+    (0..text.len()).step_by(2)
+                   .map(|i| u8::from_str_radix(&text[i..i + 2], 16).ok())
+                   .collect()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+// Waits for the next $packet#checksum, acknowledging it, or returns None
+// when the connection closes. Acks and interrupts between packets are
+// skipped
+fn read_packet<S: Read + Write>(stream: &mut S) -> io::Result<Option<String>> {
+    loop {
+        let mut packet = Vec::new();
+
+        loop {
+            match read_byte(stream)? {
+                Some(b'$') => break,
+                Some(_)    => continue,
+                None       => return Ok(None),
+            }
+        }
+
+        loop {
+            match read_byte(stream)? {
+                Some(b'#') => break,
+                Some(byte) => packet.push(byte),
+                None       => return Ok(None),
+            }
+        }
+
+        let mut sum = [0; 2];
+        stream.read_exact(&mut sum)?;
+
+        let packet = String::from_utf8_lossy(&packet).into_owned();
+        let expected = u8::from_str_radix(&String::from_utf8_lossy(&sum), 16).ok();
+
+        if expected == Some(checksum(&packet)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(packet));
+        }
+
+        // a bad checksum asks the client to send it again
+        stream.write_all(b"-")?;
+    }
+}
+
+fn write_packet<W: Write>(stream: &mut W, data: &str) -> io::Result<()> {
+    write!(stream, "${}#{:02x}", data, checksum(data))?;
+    stream.flush()
+}
+
+fn read_byte<R: Read>(stream: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+
+    match stream.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// Checks, without waiting, whether the client has asked to break in
+fn interrupted(stream: &mut TcpStream) -> io::Result<bool> {
+    stream.set_nonblocking(true)?;
+    let mut byte = [0];
+    let result = stream.read(&mut byte);
+    stream.set_nonblocking(false)?;
+
+    match result {
+        Ok(1)                                           => Ok(byte[0] == INTERRUPT),
+        Ok(_)                                           => Ok(false),
+        Err(ref error) if error.kind() == ErrorKind::WouldBlock => Ok(false),
+        Err(error)                                      => Err(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use test_atari;
+
+    // Sends each packet and collects the replies, acking as GDB does
+    fn client(port: u16, packets: Vec<&'static str>) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream.set_nodelay(true).unwrap();
+            let mut replies = Vec::new();
+
+            for packet in packets {
+                write_packet(&mut stream, packet).unwrap();
+
+                let mut ack = [0];
+                stream.read_exact(&mut ack).unwrap();
+                assert_eq!(ack[0], b'+');
+
+                if packet == "k" {
+                    break;
+                }
+
+                replies.push(read_packet(&mut stream).unwrap().unwrap());
+            }
+
+            replies
+        })
+    }
+
+    // LDX #$03, loop: STX $80 / DEX / BNE loop, then NOPs
+    fn served(packets: Vec<&'static str>) -> (Atari2600, Vec<String>) {
+        let mut atari = test_atari(&[0xA2, 0x03, 0x86, 0x80, 0xCA, 0xD0, 0xFB]);
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = client(listener.local_addr().unwrap().port(), packets);
+        let (stream, _) = listener.accept().unwrap();
+
+        GdbServer::new().serve(&mut atari, stream).unwrap();
+        (atari, client.join().unwrap())
+    }
+
+    #[test]
+    fn registers() {
+        let (atari, replies) = served(vec!["g", "P0=42", "p0", "P5=02f0", "G0102032404fff0", "k"]);
+
+        assert_eq!(replies, vec!["00000024fd00f0", "OK", "42", "OK", "OK"]);
+        assert_eq!(atari.cpu().a(), 0x01);
+        assert_eq!(atari.cpu().sp(), 0x04);
+        assert_eq!(atari.cpu().pc(), 0xF0FF);
+    }
+
+    #[test]
+    fn memory() {
+        let (mut atari, replies) = served(vec!["mf000,3", "M80,2:1234", "m80,2", "k"]);
+
+        assert_eq!(replies, vec!["a20386", "OK", "1234"]);
+        assert_eq!(atari.bus().read(0x0081), 0x34);
+    }
+
+    #[test]
+    fn step_and_breakpoints() {
+        let (atari, replies) = served(vec!["s", "Z0,f007,1", "c", "p1", "z0,f007,1", "s", "D"]);
+
+        assert_eq!(replies, vec!["S05", "OK", "S05", "00", "OK", "S05", "OK"]);
+        assert_eq!(atari.cpu().pc(), 0xF008);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (atari, replies) = served(vec!["Z2,80,1", "c", "c", "Z3,f007,1", "Z1,f000,1", "k"]);
+
+        assert_eq!(replies[1], "T05watch:0080;");
+        assert_eq!(replies[2], "T05watch:0080;");
+        assert_eq!(replies[3], "OK");
+        assert_eq!(atari.cpu().x(), 2);
+    }
+
+    #[test]
+    fn target_description() {
+        let (_, replies) = served(vec!["qSupported:multiprocess+", "qXfer:features:read:target.xml:0,10", "k"]);
+
+        assert_eq!(replies[0], "PacketSize=1000;qXfer:features:read+");
+        assert_eq!(replies[1], "m<?xml version=\"1");
+    }
+
+    #[test]
+    fn unknown_packets() {
+        let (_, replies) = served(vec!["\u{e9}0", "", "k"]);
+
+        assert_eq!(replies, vec!["", ""]);
+    }
+
+    #[test]
+    fn packets() {
+        assert_eq!(checksum("OK"), 0x9A);
+        assert_eq!(unhex("0aff"), Some(vec![0x0A, 0xFF]));
+        assert_eq!(unhex("0af"), None);
+        assert_eq!(address_length("f000,10"), Some((0xF000, 0x10)));
+    }
+}
@@ -1,33 +1,137 @@
+pub mod bus;
+pub mod cartridge;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod gdb;
 pub mod pia;
+pub mod rewind;
+pub mod state;
 pub mod tia;
+pub mod trace;
 
-use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read, Write};
 
+use bus::AtariBus;
 use cpu::Mos6507;
-use pia::Pia6532;
-use tia::Tia1A;
+use trace::Tracer;
 
-pub type Cartridge = Vec<u8>;
+pub use cartridge::Cartridge;
 
 pub struct Atari2600 {
     cpu: Mos6507,
-    pia: Pia6532,
-    tia: Tia1A,
+    bus: AtariBus,
+}
+
+impl Default for Atari2600 {
+    fn default() -> Atari2600 {
+        Atari2600::new()
+    }
 }
 
 impl Atari2600 {
     pub fn new() -> Atari2600 {
         Atari2600 {
             cpu: Mos6507::new(),
-            pia: Pia6532::new(),
-            tia: Tia1A::new(),
+            bus: AtariBus::new(Cartridge::default()),
         }
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn power_on(&mut self, cartridge: Cartridge) -> Result<i32,()> {
-        self.cpu.run(&self.pia, &self.tia, &cartridge);        
+        self.bus = AtariBus::new(cartridge);
+        self.cpu.run(&mut self.bus);
         Ok(0)
     }
+
+    // Swaps in a cartridge and resets the CPU, without running it
+    pub fn insert_cartridge(&mut self, cartridge: Cartridge) {
+        self.bus = AtariBus::new(cartridge);
+        self.cpu.reset(&mut self.bus);
+    }
+
+    // Swaps in a cartridge with the CPU still held in reset, so a debugger
+    // can look around before the reset sequence runs
+    pub fn plug_in_cartridge(&mut self, cartridge: Cartridge) {
+        self.bus = AtariBus::new(cartridge);
+    }
+
+    // Warm reset of the CPU; RAM, the TIA, the PIA and the cartridge are
+    // left alone. This is the 6507 RESET line, not the console's RESET
+    // switch, which is just an input the game polls through the PIA.
+    pub fn reset(&mut self) {
+        self.cpu.reset(&mut self.bus);
+    }
+
+    // Runs one instruction and returns the cycles it took
+    pub fn step(&mut self) -> u8 {
+        self.cpu.step(&mut self.bus)
+    }
+
+    // Runs a single CPU cycle, returning true when it ends an instruction
+    pub fn tick(&mut self) -> bool {
+        self.cpu.tick(&mut self.bus)
+    }
+
+    // Logs each instruction the CPU executes; see Tracer
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.cpu.set_tracer(tracer);
+    }
+
+    // Saves the whole machine in the format described in the state module
+    pub fn save_state(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&state::MAGIC)?;
+        state::write_u8(out, state::VERSION)?;
+        self.cpu.save_state(out)?;
+        self.bus.save_state(out)
+    }
+
+    // Restores a state saved with the same cartridge inserted. Nothing
+    // changes if the state can't be read
+    pub fn load_state(&mut self, input: &mut impl Read) -> io::Result<()> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if magic != state::MAGIC {
+            return Err(state::invalid("not a save state"));
+        }
+        if state::read_u8(input)? != state::VERSION {
+            return Err(state::invalid("unsupported version"));
+        }
+
+        let mut cpu = self.cpu.clone();
+        let mut bus = self.bus.clone();
+        cpu.load_state(input)?;
+        bus.load_state(input)?;
+
+        self.cpu = cpu;
+        self.bus = bus;
+        Ok(())
+    }
+
+    pub fn cpu(&self) -> &Mos6507 {
+        &self.cpu
+    }
+
+    pub fn bus(&mut self) -> &mut AtariBus {
+        &mut self.bus
+    }
+
+    // Both halves at once, for debuggers that drive the CPU themselves
+    pub fn parts(&mut self) -> (&mut Mos6507, &mut AtariBus) {
+        (&mut self.cpu, &mut self.bus)
+    }
+}
+
+// A machine with a 4K cartridge inserted that runs program from $F000,
+// with NOPs after it, shared by the tests
+#[cfg(test)]
+fn test_atari(program: &[u8]) -> Atari2600 {
+    let mut rom = vec![0xEA; 0x1000];
+    rom[..program.len()].copy_from_slice(program);
+    rom[0x0FFC] = 0x00;
+    rom[0x0FFD] = 0xF0;
+
+    let mut atari = Atari2600::new();
+    atari.insert_cartridge(Cartridge::new(rom).unwrap());
+    atari
 }
@@ -2,22 +2,219 @@ extern crate twenty_six;
 
 use std::env;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, BufRead, BufReader, BufWriter, LineWriter, Read, Write};
+use std::net::TcpListener;
+use std::num::ParseIntError;
+use std::ops::RangeInclusive;
+use std::process;
 use twenty_six::Atari2600;
 use twenty_six::Cartridge;
+use twenty_six::bus::Bus;
+use twenty_six::cartridge::BANK_SIZE;
+use twenty_six::debugger::Debugger;
+use twenty_six::disasm;
+use twenty_six::gdb::GdbServer;
+use twenty_six::rewind::{self, Rewind};
+use twenty_six::trace::Tracer;
+
+const USAGE: &str = "usage: twenty_six [--debug [--rewind-budget <KiB>] | --gdb <port> | --frames <n> [--save-state <file>]] [--load-state <file>] [--trace <file> [--trace-addresses F000-FFFF] [--trace-frames 0-10]] <rom>
+       twenty_six disasm <rom>";
+
+// the largest bank switched cartridges hold 64K
+const MAX_ROM_SIZE: usize = 0x10000;
+
+// --frames gives up on a ROM that never turns on VSYNC once it has run
+// this many cycles a frame, twice a PAL frame's 312 lines of 76
+const FRAME_CYCLE_LIMIT: u64 = 2 * 312 * 76;
 
 fn main() {
 
     //TODO - error checking on # of args, etc.
     let args = env::args().collect::<Vec<String>>();
-    let file = File::open(&args[1]).unwrap();
-    
-    let cartridge: Cartridge = file.bytes()
-                                .map(|x| x.unwrap())
-                                .collect(); 
+
+    if args.len() > 2 && args[1] == "disasm" {
+        // bank switched ROMs can be listed, even though they can't be run
+        if let Err(error) = disassemble(&read_rom(&args[2])) {
+            eprintln!("{}: {}", args[2], error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let mut rom_path = None;
+    let mut trace_path = None;
+    let mut trace_addresses = None;
+    let mut trace_frames = None;
+    let mut debug = false;
+    let mut gdb_port = None;
+    let mut state_path = None;
+    let mut save_path = None;
+    let mut frames = None;
+    let mut rewind_budget = rewind::DEFAULT_BUDGET;
+
+    let mut options = args.iter().skip(1);
+    while let Some(option) = options.next() {
+        match option.as_str() {
+            "--debug"           => debug = true,
+            "--frames"          => frames = options.next().map(|frames| frames.parse::<u32>().expect(USAGE)),
+            "--gdb"             => gdb_port = options.next().map(|port| port.parse::<u16>().expect(USAGE)),
+            "--load-state"      => state_path = options.next(),
+            "--save-state"      => save_path = options.next(),
+            "--rewind-budget"   => rewind_budget = options.next().and_then(|kib| kib.parse::<usize>().ok())
+                                                         .expect(USAGE) * 1024,
+            "--trace"           => trace_path = options.next(),
+            "--trace-addresses" => trace_addresses = options.next().map(|range| parse_range(range, |bound| u16::from_str_radix(bound, 16))),
+            "--trace-frames"    => trace_frames = options.next().map(|range| parse_range(range, |bound| bound.parse::<u32>())),
+            _ if option.starts_with("--") => panic!("unknown option {}\n{}", option, USAGE),
+            _                   => rom_path = Some(option),
+        }
+    }
+
+    let rom_path = rom_path.expect(USAGE);
+    // without a number of frames to run the state would never be saved
+    if save_path.is_some() && frames.is_none() {
+        panic!("{}", USAGE);
+    }
+
+    let cartridge = load_cartridge(rom_path);
      
     let mut atari_2600 =  Atari2600::new();
-    let exit_code = atari_2600.power_on(cartridge).unwrap();
+
+    if let Some(path) = trace_path {
+        // line buffered, so nothing is lost when the emulator is killed
+        let mut tracer = Tracer::new(LineWriter::new(File::create(path).unwrap()));
+
+        if let Some(addresses) = trace_addresses {
+            tracer.set_address_range(addresses);
+        }
+        if let Some(frames) = trace_frames {
+            tracer.set_frame_range(frames);
+        }
+
+        atari_2600.set_tracer(Some(tracer));
+    }
+
+    match state_path {
+        // a saved state picks up from wherever it was saved, without a reset
+        Some(path) => {
+            atari_2600.insert_cartridge(cartridge);
+            atari_2600.load_state(&mut BufReader::new(File::open(path).unwrap())).unwrap();
+        },
+        None if debug => {
+            atari_2600.plug_in_cartridge(cartridge);
+            println!("CPU held in reset; type reset to start it, or help");
+        },
+        None          => atari_2600.insert_cartridge(cartridge),
+    }
+
+    if debug {
+        debug_prompt(&mut atari_2600, Rewind::new(rewind_budget, rewind::DEFAULT_KEYFRAME_INTERVAL));
+    } else if let Some(port) = gdb_port {
+        serve_gdb(&mut atari_2600, port);
+    } else {
+        // runs for good unless it's told how many frames to run
+        let end_frame = frames.map(|frames| atari_2600.bus().beam().frame.wrapping_add(frames));
+        let cycle_limit = frames.map(|frames| frames as u64 * FRAME_CYCLE_LIMIT);
+        let mut cycles = 0;
+
+        while Some(atari_2600.bus().beam().frame) != end_frame {
+            cycles += atari_2600.step() as u64;
+
+            if matches!(cycle_limit, Some(limit) if cycles > limit) {
+                eprintln!("gave up after {} cycles without reaching frame {}", cycles, end_frame.unwrap());
+                process::exit(1);
+            }
+        }
+
+        if let Some(path) = save_path {
+            atari_2600.save_state(&mut BufWriter::new(File::create(path).unwrap())).unwrap();
+        }
+    }
+}
+
+fn read_rom(path: &str) -> Vec<u8> {
+    let mut file = File::open(path).unwrap();
     
-    println!("Atari2600: Exited with result {}", exit_code);
+    let mut rom = Vec::new();
+    file.read_to_end(&mut rom).unwrap();
+    rom
+}
+
+fn load_cartridge(path: &str) -> Cartridge {
+    Cartridge::new(read_rom(path)).unwrap_or_else(|error| {
+        eprintln!("{}: {}", path, error);
+        process::exit(1);
+    })
+}
+
+// Reads debugger commands from stdin until quit or end of input
+fn debug_prompt(atari_2600: &mut Atari2600, rewind: Rewind) {
+    let mut debugger = Debugger::with_rewind(rewind);
+    let stdin = io::stdin();
+
+    print!("> ");
+    io::stdout().flush().unwrap();
+
+    for line in stdin.lock().lines() {
+        let line = line.unwrap();
+
+        match line.trim() {
+            "q" | "quit" => break,
+            command      => {
+                let output = debugger.execute(atari_2600, command);
+                if !output.is_empty() {
+                    println!("{}", output);
+                }
+            },
+        }
+
+        print!("> ");
+        io::stdout().flush().unwrap();
+    }
+}
+
+// Waits on a local port for a remote debugger, serving one client after
+// another with the machine stopped in between
+fn serve_gdb(atari_2600: &mut Atari2600, port: u16) {
+    let listener = TcpListener::bind(("127.0.0.1", port)).unwrap();
+    let mut server = GdbServer::new();
+
+    println!("Waiting for a GDB client on 127.0.0.1:{}", port);
+
+    for stream in listener.incoming() {
+        if let Err(error) = server.serve(atari_2600, stream.unwrap()) {
+            println!("GDB connection lost: {}", error);
+        }
+    }
+}
+
+// Parses an inclusive range like F000-F0FF, or a single value
+fn parse_range<T: Copy>(range: &str, parse: fn(&str) -> Result<T, ParseIntError>) -> RangeInclusive<T> {
+    let mut bounds = range.splitn(2, '-')
+                          .map(|bound| parse(bound).expect(USAGE));
+    let start = bounds.next().unwrap();
+    let end = bounds.next().unwrap_or(start);
+
+    start..=end
+}
+
+// Prints a listing of every bank, each placed where it runs at the top of
+// the address space
+fn disassemble(rom: &[u8]) -> Result<(), String> {
+    if rom.len() > MAX_ROM_SIZE {
+        return Err(format!("{} byte ROM is larger than any cartridge", rom.len()));
+    }
+
+    let banks = rom.chunks(BANK_SIZE).collect::<Vec<&[u8]>>();
+
+    for (bank, bytes) in banks.iter().enumerate() {
+        let origin = (0x10000 - bytes.len()) as u16;
+
+        if banks.len() > 1 {
+            println!("; bank {} of {}", bank, banks.len());
+        }
+        print!("{}", disasm::listing(bytes, origin));
+    }
+
+    Ok(())
 }
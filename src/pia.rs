@@ -1,19 +1,172 @@
+use std::io::{self, Read, Write};
+
+use state;
+
+// timer intervals, as a shift of the cycle count
+const TIM1T_SHIFT: u8       = 0;
+const TIM8T_SHIFT: u8       = 3;
+const TIM64T_SHIFT: u8      = 6;
+const T1024T_SHIFT: u8      = 10;
+
+// for the interrupt flag register (TIMINT)
+const TIMER_FLAG_MASK: u8   = 0x80;
+
+// joystick and console switch inputs read high when nothing is pressed;
+// port 2 has the color switch on and both difficulties set to B
+const PORT1_DEFAULT: u8     = 0xFF;
+const PORT2_DEFAULT: u8     = 0x0B;
+
+#[derive(Clone)]
 pub struct Pia6532 {
     ram: [u8; 128],
     port1: u8,
     port2: u8,
-    pit_timer: i32
+    port1_ddr: u8,
+    port2_ddr: u8,
+    port1_output: u8,
+    port2_output: u8,
+    pit_timer: i32,
+    timer_shift: u8,
+    interrupt_flags: u8,
+}
+
+impl Default for Pia6532 {
+    fn default() -> Pia6532 {
+        Pia6532::new()
+    }
 }
 
 impl Pia6532 {
     pub fn new() -> Pia6532 {
         Pia6532 {
-            ram: [0; 128],            
-            port1: 0,            
-            port2: 0,
+            ram: [0; 128],
+            port1: PORT1_DEFAULT,
+            port2: PORT2_DEFAULT,
+            port1_ddr: 0,
+            port2_ddr: 0,
+            port1_output: 0,
+            port2_output: 0,
             pit_timer: 0,
+            timer_shift: T1024T_SHIFT,
+            interrupt_flags: 0,
+        }
+    }
+
+    pub fn read_ram(&self, address: u16) -> u8 {
+        self.ram[(address & 0x7F) as usize]
+    }
+
+    pub fn write_ram(&mut self, address: u16, data: u8) {
+        self.ram[(address & 0x7F) as usize] = data;
+    }
+
+    // Reads the I/O and timer registers, decoded from A0-A2
+    pub fn read(&mut self, address: u16) -> u8 {
+        let data = self.peek(address);
+
+        // reading INTIM acknowledges the timer interrupt
+        if (address & 0x05) == 0x04 {
+            self.interrupt_flags &= !TIMER_FLAG_MASK;
+        }
+
+        data
+    }
+
+    // Reads the I/O and timer registers without acknowledging interrupts
+    pub fn peek(&self, address: u16) -> u8 {
+        if (address & 0x04) == 0 {
+            match address & 0x03 {
+                0x00 => (self.port1_output & self.port1_ddr) | (self.port1 & !self.port1_ddr),
+                0x01 => self.port1_ddr,
+                0x02 => (self.port2_output & self.port2_ddr) | (self.port2 & !self.port2_ddr),
+                _    => self.port2_ddr,
+            }
+        } else if (address & 0x01) == 0 {
+            self.intim()
+        } else {
+            self.interrupt_flags
+        }
+    }
+
+    // Writes the I/O and timer registers, decoded from A0-A4. The PA7 edge
+    // detect isn't emulated, so writes to it are ignored
+    pub fn write(&mut self, address: u16, data: u8) {
+        if (address & 0x04) == 0 {
+            match address & 0x03 {
+                0x00 => self.port1_output = data,
+                0x01 => self.port1_ddr = data,
+                0x02 => self.port2_output = data,
+                _    => self.port2_ddr = data,
+            }
+        } else if (address & 0x10) != 0 {
+            self.timer_shift = match address & 0x03 {
+                0x00 => TIM1T_SHIFT,
+                0x01 => TIM8T_SHIFT,
+                0x02 => TIM64T_SHIFT,
+                _    => T1024T_SHIFT,
+            };
+
+            // the first decrement happens on the very next cycle
+            self.pit_timer = (data as i32) << self.timer_shift;
+            self.interrupt_flags &= !TIMER_FLAG_MASK;
         }
     }
+
+    // Advances the interval timer by the given number of CPU cycles
+    pub fn tick(&mut self, cycles: u8) {
+        for _ in 0..cycles {
+            self.pit_timer -= 1;
+
+            if self.pit_timer < 0 {
+                // once expired the timer keeps counting down once per cycle
+                self.interrupt_flags |= TIMER_FLAG_MASK;
+                self.timer_shift = TIM1T_SHIFT;
+                self.pit_timer = 0xFF;
+            }
+        }
+    }
+
+    pub fn save_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        state::write_bytes(out, &self.ram)?;
+        for register in &[self.port1, self.port2, self.port1_ddr, self.port2_ddr, self.port1_output,
+                          self.port2_output, self.timer_shift, self.interrupt_flags] {
+            state::write_u8(out, *register)?;
+        }
+        state::write_u32(out, self.pit_timer as u32)
+    }
+
+    pub fn load_state<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        let ram = state::read_bytes(input, self.ram.len())?;
+        self.ram.copy_from_slice(&ram);
+        self.port1 = state::read_u8(input)?;
+        self.port2 = state::read_u8(input)?;
+        self.port1_ddr = state::read_u8(input)?;
+        self.port2_ddr = state::read_u8(input)?;
+        self.port1_output = state::read_u8(input)?;
+        self.port2_output = state::read_u8(input)?;
+        self.timer_shift = state::read_u8(input)?;
+        self.interrupt_flags = state::read_u8(input)?;
+        self.pit_timer = state::read_u32(input)? as i32;
+
+        if self.timer_shift > T1024T_SHIFT {
+            return Err(state::invalid("bad timer interval"));
+        }
+        Ok(())
+    }
+
+    // Sets the levels on the port 1 pins (SWCHA), which are the joysticks
+    pub fn set_port1(&mut self, value: u8) {
+        self.port1 = value;
+    }
+
+    // Sets the levels on the port 2 pins (SWCHB), which are the console switches
+    pub fn set_port2(&mut self, value: u8) {
+        self.port2 = value;
+    }
+
+    fn intim(&self) -> u8 {
+        (self.pit_timer >> self.timer_shift) as u8
+    }
 }
 
 #[cfg(test)]
@@ -21,7 +174,103 @@ mod tests {
     use super::*;
 
     #[test]
-    fn it_works() {
-        assert_eq!(4, 4);
+    fn ram() {
+        let mut pia = Pia6532::new();
+
+        pia.write_ram(0x80, 0x42);
+
+        assert_eq!(pia.read_ram(0x00), 0x42);
+        assert_eq!(pia.read_ram(0x80), 0x42);
+    }
+
+    #[test]
+    fn ports_read_inputs() {
+        let mut pia = Pia6532::new();
+
+        assert_eq!(pia.read(0x00), PORT1_DEFAULT);
+        assert_eq!(pia.read(0x02), PORT2_DEFAULT);
+
+        pia.set_port1(0x7F);
+
+        assert_eq!(pia.read(0x00), 0x7F);
+    }
+
+    #[test]
+    fn ports_read_outputs() {
+        let mut pia = Pia6532::new();
+
+        pia.write(0x01, 0xF0);
+        pia.write(0x00, 0x00);
+
+        // output bits come from the latch, input bits from the pins
+        assert_eq!(pia.read(0x00), 0x0F);
+        assert_eq!(pia.read(0x01), 0xF0);
+    }
+
+    #[test]
+    fn timer() {
+        let mut pia = Pia6532::new();
+
+        pia.write(0x16, 10); // TIM64T
+        pia.tick(1);
+
+        assert_eq!(pia.read(0x04), 9);
+
+        pia.tick(64);
+
+        assert_eq!(pia.read(0x04), 8);
+    }
+
+    #[test]
+    fn timer_interval() {
+        let mut pia = Pia6532::new();
+
+        pia.write(0x15, 2); // TIM8T
+        pia.tick(1);
+        pia.tick(7);
+
+        assert_eq!(pia.read(0x04), 1);
+
+        pia.tick(1);
+
+        assert_eq!(pia.read(0x04), 0);
+    }
+
+    #[test]
+    fn timer_expired() {
+        let mut pia = Pia6532::new();
+
+        pia.write(0x17, 1); // T1024T
+        pia.tick(255);
+        pia.tick(255);
+        pia.tick(255);
+        pia.tick(255);
+        pia.tick(4);
+
+        assert_eq!(pia.read(0x05) & TIMER_FLAG_MASK, 0);
+
+        pia.tick(1);
+
+        // once expired the timer wraps and counts down every cycle
+        assert_eq!(pia.read(0x05) & TIMER_FLAG_MASK, TIMER_FLAG_MASK);
+        assert_eq!(pia.read(0x04), 0xFF);
+
+        pia.tick(1);
+
+        assert_eq!(pia.read(0x04), 0xFE);
+    }
+
+    #[test]
+    fn timer_flag_cleared_by_intim() {
+        let mut pia = Pia6532::new();
+
+        pia.write(0x14, 0); // TIM1T
+        pia.tick(2);
+
+        assert_eq!(pia.read(0x05) & TIMER_FLAG_MASK, TIMER_FLAG_MASK);
+
+        pia.read(0x04);
+
+        assert_eq!(pia.read(0x05) & TIMER_FLAG_MASK, 0);
     }
 }
@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+
+use Atari2600;
+
+pub const DEFAULT_BUDGET: usize             = 4 * 1024 * 1024;
+pub const DEFAULT_KEYFRAME_INTERVAL: usize  = 60;
+
+// A full snapshot and the ones taken after it, each stored as the XOR
+// against the keyframe, run length encoded. Most of the machine doesn't
+// change from one frame to the next, so deltas are mostly zero runs
+struct Group {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+impl Group {
+    fn size(&self) -> usize {
+        self.keyframe.len() + self.deltas.iter().map(|delta| delta.len()).sum::<usize>()
+    }
+}
+
+// A ring buffer of save states for stepping backwards, usually pushed
+// once a frame. The oldest keyframe and its deltas are dropped together
+// whenever the buffer goes over its memory budget
+pub struct Rewind {
+    groups: VecDeque<Group>,
+    budget: usize,
+    keyframe_interval: usize,
+    used: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Rewind {
+        Rewind::new(DEFAULT_BUDGET, DEFAULT_KEYFRAME_INTERVAL)
+    }
+}
+
+impl Rewind {
+    // The budget is in bytes; a keyframe is taken every keyframe_interval
+    // snapshots
+    pub fn new(budget: usize, keyframe_interval: usize) -> Rewind {
+        Rewind {
+            groups: VecDeque::new(),
+            budget,
+            keyframe_interval: keyframe_interval.max(1),
+            used: 0,
+        }
+    }
+
+    pub fn push(&mut self, atari: &Atari2600) {
+        let mut state = Vec::new();
+        atari.save_state(&mut state).unwrap();
+
+        let start_group = match self.groups.back() {
+            Some(group) => group.deltas.len() + 1 >= self.keyframe_interval,
+            None        => true,
+        };
+
+        if start_group {
+            self.used += state.len();
+            self.groups.push_back(Group { keyframe: state, deltas: Vec::new() });
+        } else {
+            let group = self.groups.back_mut().unwrap();
+            let delta = compress(&state, &group.keyframe);
+            self.used += delta.len();
+            group.deltas.push(delta);
+        }
+
+        // always keep the newest group, even if it alone is over budget
+        while self.used > self.budget && self.groups.len() > 1 {
+            let oldest = self.groups.pop_front().unwrap();
+            self.used -= oldest.size();
+        }
+    }
+
+    // Restores the newest snapshot and drops it, so each call goes back
+    // one more. Returns false when there's nothing left to go back to
+    pub fn step_back(&mut self, atari: &mut Atari2600) -> bool {
+        let state = match self.groups.back_mut() {
+            Some(group) => match group.deltas.pop() {
+                Some(delta) => {
+                    self.used -= delta.len();
+                    decompress(&delta, &group.keyframe)
+                },
+                None        => Vec::new(),
+            },
+            None        => return false,
+        };
+
+        let state = if state.is_empty() {
+            let group = self.groups.pop_back().unwrap();
+            self.used -= group.keyframe.len();
+            group.keyframe
+        } else {
+            state
+        };
+
+        atari.load_state(&mut &state[..]).unwrap();
+        true
+    }
+
+    // How many snapshots there are to go back through
+    pub fn len(&self) -> usize {
+        self.groups.iter().map(|group| group.deltas.len() + 1).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    // Bytes held by the snapshots
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.used = 0;
+    }
+}
+
+// Encodes data XORed with the keyframe as its length, then pairs of a
+// zero run and a run of literal bytes. Past the keyframe's end its bytes
+// count as 0, since states aren't all the same length
+fn compress(data: &[u8], keyframe: &[u8]) -> Vec<u8> {
+    let xored = data.iter().enumerate()
+                           .map(|(i, byte)| byte ^ keyframe.get(i).cloned().unwrap_or(0))
+                           .collect::<Vec<u8>>();
+
+    let mut out = Vec::new();
+    write_varint(&mut out, xored.len());
+
+    let mut position = 0;
+    while position < xored.len() {
+        let zeros = xored[position..].iter().take_while(|byte| **byte == 0).count();
+        position += zeros;
+
+        let literals = xored[position..].iter().take_while(|byte| **byte != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend_from_slice(&xored[position..position + literals]);
+        position += literals;
+    }
+
+    out
+}
+
+fn decompress(delta: &[u8], keyframe: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let length = read_varint(delta, &mut position);
+    let mut data = Vec::with_capacity(length);
+
+    while position < delta.len() {
+        let zeros = read_varint(delta, &mut position);
+        data.resize(data.len() + zeros, 0);
+
+        let literals = read_varint(delta, &mut position);
+        data.extend_from_slice(&delta[position..position + literals]);
+        position += literals;
+    }
+
+    data.iter().enumerate()
+               .map(|(i, byte)| byte ^ keyframe.get(i).cloned().unwrap_or(0))
+               .collect()
+}
+
+// seven bits at a time, low first, with the top bit set on all but the last
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = data[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bus::Bus;
+    use test_atari;
+
+    // LDX #$00, loop: INX / STX $80 / JMP loop
+    fn counting_atari() -> Atari2600 {
+        test_atari(&[0xA2, 0x00, 0xE8, 0x86, 0x80, 0x4C, 0x02, 0xF0])
+    }
+
+    #[test]
+    fn compression() {
+        let keyframe = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let data = vec![1, 2, 9, 4, 5, 6, 7, 8, 10];
+        let delta = compress(&data, &keyframe);
+
+        assert_eq!(delta, vec![9, 2, 1, 3 ^ 9, 5, 1, 10]);
+        assert_eq!(decompress(&delta, &keyframe), data);
+        assert_eq!(decompress(&compress(&keyframe[..4], &keyframe), &keyframe), &keyframe[..4]);
+    }
+
+    #[test]
+    fn varint() {
+        let mut out = Vec::new();
+        write_varint(&mut out, 300);
+
+        assert_eq!(out, vec![0xAC, 0x02]);
+        assert_eq!(read_varint(&out, &mut 0), 300);
+    }
+
+    #[test]
+    fn step_back() {
+        let mut atari = counting_atari();
+        let mut rewind = Rewind::new(DEFAULT_BUDGET, 3);
+
+        for _ in 0..5 {
+            rewind.push(&atari);
+            for _ in 0..3 {
+                atari.step();
+            }
+        }
+
+        assert_eq!(rewind.len(), 5);
+
+        // every three steps store one more count
+        for count in (0..5).rev() {
+            assert!(rewind.step_back(&mut atari));
+            assert_eq!(atari.bus().peek(0x0080), count);
+        }
+
+        assert!(!rewind.step_back(&mut atari));
+        assert_eq!(rewind.used(), 0);
+    }
+
+    #[test]
+    fn budget() {
+        let mut atari = counting_atari();
+        let mut state = Vec::new();
+        atari.save_state(&mut state).unwrap();
+
+        // room for a bit more than one keyframe and its deltas
+        let mut rewind = Rewind::new(state.len() + 100, 4);
+
+        for _ in 0..12 {
+            rewind.push(&atari);
+            atari.step();
+        }
+
+        assert_eq!(rewind.len(), 4);
+        assert!(rewind.used() <= state.len() + 100);
+    }
+}
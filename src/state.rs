@@ -0,0 +1,117 @@
+use std::io::{self, Read, Write};
+
+// Save states start with the magic number and a version, followed by the
+// CPU, PIA, TIA, cartridge and data bus in that order. Everything is
+// little endian
+pub const MAGIC: [u8; 4]    = *b"A26S";
+pub const VERSION: u8       = 6;
+
+pub fn write_u8<W: Write>(out: &mut W, value: u8) -> io::Result<()> {
+    out.write_all(&[value])
+}
+
+pub fn write_bool<W: Write>(out: &mut W, value: bool) -> io::Result<()> {
+    write_u8(out, value as u8)
+}
+
+pub fn write_u16<W: Write>(out: &mut W, value: u16) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn write_u32<W: Write>(out: &mut W, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+pub fn write_u64<W: Write>(out: &mut W, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+// A length prefixed block of bytes
+pub fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_u32(out, bytes.len() as u32)?;
+    out.write_all(bytes)
+}
+
+pub fn read_u8<R: Read>(input: &mut R) -> io::Result<u8> {
+    let mut buffer = [0; 1];
+    input.read_exact(&mut buffer)?;
+    Ok(buffer[0])
+}
+
+pub fn read_bool<R: Read>(input: &mut R) -> io::Result<bool> {
+    match read_u8(input)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(invalid("bad boolean")),
+    }
+}
+
+pub fn read_u16<R: Read>(input: &mut R) -> io::Result<u16> {
+    let mut buffer = [0; 2];
+    input.read_exact(&mut buffer)?;
+    Ok(u16::from_le_bytes(buffer))
+}
+
+pub fn read_u32<R: Read>(input: &mut R) -> io::Result<u32> {
+    let mut buffer = [0; 4];
+    input.read_exact(&mut buffer)?;
+    Ok(u32::from_le_bytes(buffer))
+}
+
+pub fn read_u64<R: Read>(input: &mut R) -> io::Result<u64> {
+    let mut buffer = [0; 8];
+    input.read_exact(&mut buffer)?;
+    Ok(u64::from_le_bytes(buffer))
+}
+
+// Reads a block written by write_bytes, which has to be the expected length
+pub fn read_bytes<R: Read>(input: &mut R, expected: usize) -> io::Result<Vec<u8>> {
+    if read_u32(input)? as usize != expected {
+        return Err(invalid("block is the wrong size"));
+    }
+
+    let mut bytes = vec![0; expected];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("bad save state: {}", message))
+}
+
+// FNV-1a, to tell whether a state belongs to the cartridge it's loaded with
+pub fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811C_9DC5, |hash, byte| (hash ^ *byte as u32).wrapping_mul(0x0100_0193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut out = Vec::new();
+        write_u8(&mut out, 0x12).unwrap();
+        write_bool(&mut out, true).unwrap();
+        write_u16(&mut out, 0x3456).unwrap();
+        write_u64(&mut out, 0x0123_4567_89AB_CDEF).unwrap();
+        write_bytes(&mut out, &[1, 2, 3]).unwrap();
+
+        let mut input = &out[..];
+
+        assert_eq!(read_u8(&mut input).unwrap(), 0x12);
+        assert!(read_bool(&mut input).unwrap());
+        assert_eq!(read_u16(&mut input).unwrap(), 0x3456);
+        assert_eq!(read_u64(&mut input).unwrap(), 0x0123_4567_89AB_CDEF);
+        assert_eq!(read_bytes(&mut input, 3).unwrap(), vec![1, 2, 3]);
+        assert!(read_u8(&mut input).is_err());
+    }
+
+    #[test]
+    fn wrong_block_size() {
+        let mut out = Vec::new();
+        write_bytes(&mut out, &[1, 2, 3]).unwrap();
+
+        assert!(read_bytes(&mut &out[..], 4).is_err());
+    }
+}
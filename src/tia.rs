@@ -1,23 +1,1210 @@
-use std::collections::HashMap;
+use std::io::{self, Read, Write};
 
+use state;
+
+// color clocks per scanline, including horizontal blank
+const COLOR_CLOCKS_PER_LINE: u8 = 228;
+
+// the first visible color clock, after horizontal blank
+const HBLANK_CLOCKS: u8         = 68;
+const VISIBLE_PIXELS: u8        = 160;
+
+// the frame buffer has room for a PAL frame; NTSC only uses 262 lines
+pub const FRAME_WIDTH: usize    = VISIBLE_PIXELS as usize;
+pub const FRAME_HEIGHT: usize   = 312;
+
+// Objects start drawing a few pixels after they're reset, or at the
+// left edge if they're reset during horizontal blank
+const PLAYER_RESET_DELAY: u8    = 5;
+const OBJECT_RESET_DELAY: u8    = 4;
+const PLAYER_HBLANK_PIXEL: u8   = 3;
+const OBJECT_HBLANK_PIXEL: u8   = 2;
+
+// An HMOVE during horizontal blank extends it by 8 pixels, leaving the
+// black comb at the left edge
+const HMOVE_BLANK_CLOCKS: u8    = 8;
+
+// HMOVE's ripple counter starts a few color clocks after the strobe,
+// then steps once every 4 color clocks, and only gets to 15
+const HMOVE_DELAY: u8           = 6;
+const HMOVE_PULSE_MASK: u8      = 0x03;
+const HMOVE_COUNTER_MAX: u8     = 15;
+
+// write registers, decoded from A0-A5
+const VSYNC: u16                = 0x00;
+const VBLANK: u16               = 0x01;
+const WSYNC: u16                = 0x02;
+const RSYNC: u16                = 0x03;
+const NUSIZ0: u16               = 0x04;
+const NUSIZ1: u16               = 0x05;
+const COLUP0: u16               = 0x06;
+const COLUP1: u16               = 0x07;
+const COLUPF: u16               = 0x08;
+const COLUBK: u16               = 0x09;
+const CTRLPF: u16               = 0x0A;
+const REFP0: u16                = 0x0B;
+const REFP1: u16                = 0x0C;
+const PF0: u16                  = 0x0D;
+const PF1: u16                  = 0x0E;
+const PF2: u16                  = 0x0F;
+const RESP0: u16                = 0x10;
+const RESP1: u16                = 0x11;
+const RESM0: u16                = 0x12;
+const RESM1: u16                = 0x13;
+const RESBL: u16                = 0x14;
+const AUDC0: u16                = 0x15;
+const AUDC1: u16                = 0x16;
+const AUDF0: u16                = 0x17;
+const AUDF1: u16                = 0x18;
+const AUDV0: u16                = 0x19;
+const AUDV1: u16                = 0x1A;
+const GRP0: u16                 = 0x1B;
+const GRP1: u16                 = 0x1C;
+const ENAM0: u16                = 0x1D;
+const ENAM1: u16                = 0x1E;
+const ENABL: u16                = 0x1F;
+const HMP0: u16                 = 0x20;
+const HMP1: u16                 = 0x21;
+const HMM0: u16                 = 0x22;
+const HMM1: u16                 = 0x23;
+const HMBL: u16                 = 0x24;
+const VDELP0: u16               = 0x25;
+const VDELP1: u16               = 0x26;
+const VDELBL: u16               = 0x27;
+const RESMP0: u16               = 0x28;
+const RESMP1: u16               = 0x29;
+const HMOVE: u16                = 0x2A;
+const HMCLR: u16                = 0x2B;
+const CXCLR: u16                = 0x2C;
+
+// registers are decoded from A0-A5
+const REGISTER_COUNT: u16       = 0x40;
+
+// the bits each data register keeps
+const VSYNC_MASK: u8            = 0x02;
+const VBLANK_MASK: u8           = 0xC2;
+const BLANK_MASK: u8            = 0x02;
+const NUSIZ_MASK: u8            = 0x37;
+const COPIES_MASK: u8           = 0x07;
+const COLOR_MASK: u8            = 0xFE;
+const CTRLPF_MASK: u8           = 0x37;
+const REFLECT_MASK: u8          = 0x01;
+const SCORE_MASK: u8            = 0x02;
+const PRIORITY_MASK: u8         = 0x04;
+const REFP_MASK: u8             = 0x08;
+const PF0_MASK: u8              = 0xF0;
+const AUDC_MASK: u8             = 0x0F;
+const AUDF_MASK: u8             = 0x1F;
+const AUDV_MASK: u8             = 0x0F;
+const ENABLE_MASK: u8           = 0x02;
+const HM_MASK: u8               = 0xF0;
+const VDEL_MASK: u8             = 0x01;
+const RESMP_MASK: u8            = 0x02;
+
+// VBLANK dumps the paddle capacitors with D7 and latches the fire
+// buttons with D6
+const DUMP_PADDLES_MASK: u8     = 0x80;
+const LATCH_BUTTONS_MASK: u8    = 0x40;
+
+// read registers, decoded from A0-A3
+const CXBLPF: u16               = 0x06;
+const CXPPMM: u16               = 0x07;
+const INPT0: u16                = 0x08;
+const INPT3: u16                = 0x0B;
+const INPT4: u16                = 0x0C;
+const INPT5: u16                = 0x0D;
+
+// the collision latches drive D7 and D6, CXBLPF and the inputs just D7;
+// the rest of the data bus floats
+const COLLISION_DRIVEN_BITS: u8 = 0xC0;
+const INPUT_DRIVEN_BITS: u8     = 0x80;
+const INPUT_HIGH: u8            = 0x80;
+
+// objects, as indexes into the positions and bits of an object set
+pub const P0: usize             = 0;
+pub const P1: usize             = 1;
+pub const M0: usize             = 2;
+pub const M1: usize             = 3;
+pub const BL: usize             = 4;
+pub const PF: usize             = 5;
+const OBJECT_COUNT: usize       = 5;
+
+// NUSIZx's low bits choose where copies of a player and its missile
+// start, relative to its position, and how many pixels wide each of the
+// player's bits is. Scaled players start a pixel late
+const NUSIZ_COPIES: [&[u8]; 8] = [
+    &[0], &[0, 16], &[0, 32], &[0, 16, 32], &[0, 64], &[0], &[0, 32, 64], &[0],
+];
+const NUSIZ_SCALE: [u8; 8]      = [1, 1, 1, 1, 1, 2, 1, 4];
+
+// RESMPx keeps a missile in the middle of its player, by scale
+const MISSILE_LOCK_OFFSET: [u8; 5] = [0, 3, 6, 0, 10];
+
+// The pairs each collision latch watches, two to a register with D6
+// first: CXM0P has M0-P0 in D6 and M0-P1 in D7, through to CXPPMM.
+// CXBLPF's D6 latches nothing
+const COLLISION_PAIRS: [(usize, usize); 16] = [
+    (M0, P0), (M0, P1), (M1, P1), (M1, P0), (P0, BL), (P0, PF), (P1, BL), (P1, PF),
+    (M0, BL), (M0, PF), (M1, BL), (M1, PF), (BL, BL), (BL, PF), (M0, M1), (P0, P1),
+];
+
+// The collision latches set by every set of overlapping objects
+const COLLISIONS: [u16; 64] = collision_table();
+
+const fn collision_table() -> [u16; 64] {
+    let mut table = [0; 64];
+    let mut objects = 0;
+
+    while objects < 64 {
+        let mut latch = 0;
+
+        while latch < COLLISION_PAIRS.len() {
+            let (a, b) = COLLISION_PAIRS[latch];
+            if a != b && (objects >> a) & 1 != 0 && (objects >> b) & 1 != 0 {
+                table[objects] |= 1 << latch;
+            }
+            latch += 1;
+        }
+        objects += 1;
+    }
+
+    table
+}
+
+// Where the beam is. Frames start, and scanlines are counted from, the
+// point where the game turns on VSYNC
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Beam {
+    pub frame: u32,
+    pub scanline: u16,
+    pub color_clock: u8,
+}
+
+// The latched data registers, already masked to the bits the TIA keeps.
+// Pairs are indexed by player, and pf holds PF0-PF2
+#[derive(Clone, Default)]
+struct Registers {
+    vsync: u8,
+    vblank: u8,
+    nusiz: [u8; 2],
+    colup: [u8; 2],
+    colupf: u8,
+    colubk: u8,
+    ctrlpf: u8,
+    refp: [u8; 2],
+    pf: [u8; 3],
+    audc: [u8; 2],
+    audf: [u8; 2],
+    audv: [u8; 2],
+    grp: [u8; 2],
+    enam: [u8; 2],
+    enabl: u8,
+    hmp: [u8; 2],
+    hmm: [u8; 2],
+    hmbl: u8,
+    vdelp: [u8; 2],
+    vdelbl: u8,
+    resmp: [u8; 2],
+}
+
+#[derive(Clone)]
 pub struct Tia1A {
-    registers: HashMap<u16, u8>,
+    registers: Registers,
+    // the copies VDELPx and VDELBL choose, taken when the other player's
+    // graphics are written
+    old_grp: [u8; 2],
+    old_enabl: u8,
+    // horizontal positions as visible pixels, 0-159
+    positions: [u8; OBJECT_COUNT],
+    // one bit per collision latch, in COLLISION_PAIRS order
+    collisions: u16,
+    // HMOVE's ripple counter, the objects it's still moving, and whether
+    // this line's horizontal blank is extended
+    hmove_delay: u8,
+    hmove_counter: u8,
+    moving: [bool; OBJECT_COUNT],
+    hmove_blank: bool,
+    // how many color clocks each paddle's capacitor takes to charge, or
+    // None when nothing's plugged in, and how long it's been charging
+    paddles: [Option<u32>; 4],
+    charge: u32,
+    fire_buttons: [bool; 2],
+    // fire buttons pressed since VBLANK enabled the latches
+    fire_latches: [bool; 2],
+    beam: Beam,
+    wsync: bool,
+    // the color of each pixel drawn, by scanline
+    frame_buffer: Vec<u8>,
+}
+
+impl Default for Tia1A {
+    fn default() -> Tia1A {
+        Tia1A::new()
+    }
+}
+
+// The data bus bits a read from the address drives
+pub fn driven_bits(address: u16) -> u8 {
+    match address & 0x0F {
+        CXBLPF | INPT0..=INPT5 => INPUT_DRIVEN_BITS,
+        _                      => COLLISION_DRIVEN_BITS,
+    }
 }
 
 impl Tia1A {
     pub fn new() -> Tia1A {
         Tia1A {
-            registers: HashMap::new(),
+            registers: Registers::default(),
+            old_grp: [0; 2],
+            old_enabl: 0,
+            positions: [0; OBJECT_COUNT],
+            collisions: 0,
+            hmove_delay: 0,
+            hmove_counter: 0,
+            moving: [false; OBJECT_COUNT],
+            hmove_blank: false,
+            paddles: [None; 4],
+            charge: 0,
+            fire_buttons: [false; 2],
+            fire_latches: [false; 2],
+            beam: Beam::default(),
+            wsync: false,
+            frame_buffer: vec![0; FRAME_WIDTH * FRAME_HEIGHT],
+        }
+    }
+
+    // Reads are decoded from A0-A3
+    pub fn read(&mut self, address: u16) -> u8 {
+        self.peek(address)
+    }
+
+    // Only the bits driven_bits gives for the address mean anything; the
+    // bus fills in the rest from whatever was last on it
+    pub fn peek(&self, address: u16) -> u8 {
+        let address = address & 0x0F;
+
+        match address {
+            0..=CXPPMM    => ((self.collisions >> (address * 2)) as u8 & 0x03) << 6,
+            INPT0..=INPT3 => {
+                let paddle = (address - INPT0) as usize;
+                let charged = (self.registers.vblank & DUMP_PADDLES_MASK) == 0
+                              && matches!(self.paddles[paddle], Some(time) if self.charge >= time);
+                if charged { INPUT_HIGH } else { 0 }
+            },
+            INPT4 | INPT5 => {
+                let button = (address - INPT4) as usize;
+                let latched = (self.registers.vblank & LATCH_BUTTONS_MASK) != 0 && self.fire_latches[button];
+                if self.fire_buttons[button] || latched { 0 } else { INPUT_HIGH }
+            },
+            _             => 0,
+        }
+    }
+
+    // Connects a paddle whose capacitor charges the given number of
+    // scanlines after VBLANK stops dumping it, or disconnects it
+    pub fn set_paddle(&mut self, paddle: usize, scanlines: Option<u16>) {
+        self.paddles[paddle] = scanlines.map(|scanlines| scanlines as u32 * COLOR_CLOCKS_PER_LINE as u32);
+    }
+
+    pub fn set_fire_button(&mut self, button: usize, pressed: bool) {
+        self.fire_buttons[button] = pressed;
+        self.fire_latches[button] |= pressed;
+    }
+
+    // Writes are decoded from A0-A5
+    pub fn write(&mut self, address: u16, data: u8) {
+        let address = address & 0x3F;
+
+        match address {
+            // only turning VSYNC on starts a frame
+            VSYNC if (data & VSYNC_MASK) != 0 && !self.vsync() => {
+                self.beam.frame = self.beam.frame.wrapping_add(1);
+                self.beam.scanline = 0;
+            },
+            // enabling the latches resets them to the buttons' levels
+            VBLANK if (data & LATCH_BUTTONS_MASK) != 0 && (self.registers.vblank & LATCH_BUTTONS_MASK) == 0 => {
+                self.fire_latches = self.fire_buttons;
+            },
+            WSYNC  => self.wsync = true,
+            RSYNC  => self.new_line(),
+            RESP0  => self.reset_position(P0),
+            RESP1  => self.reset_position(P1),
+            RESM0  => self.reset_position(M0),
+            RESM1  => self.reset_position(M1),
+            RESBL  => self.reset_position(BL),
+            // writing either player's graphics delays the other's
+            GRP0   => self.old_grp[1] = self.registers.grp[1],
+            GRP1   => {
+                self.old_grp[0] = self.registers.grp[0];
+                self.old_enabl = self.registers.enabl;
+            },
+            HMOVE  => self.hmove(),
+            HMCLR  => {
+                self.registers.hmp = [0; 2];
+                self.registers.hmm = [0; 2];
+                self.registers.hmbl = 0;
+            },
+            CXCLR  => self.collisions = 0,
+            _      => (),
+        }
+
+        // once more with the old registers, so a missile let go by RESMPx
+        // is left where its player is now
+        self.lock_missiles();
+        self.latch(address, data);
+        self.lock_missiles();
+    }
+
+    // Missiles locked to their players by RESMPx follow them, and stay in
+    // the middle once they're let go. Anything that moves a player has to
+    // call this after
+    fn lock_missiles(&mut self) {
+        for player in 0..2 {
+            if self.registers.resmp[player] != 0 {
+                let scale = NUSIZ_SCALE[(self.registers.nusiz[player] & COPIES_MASK) as usize];
+                let position = self.positions[P0 + player] + MISSILE_LOCK_OFFSET[scale as usize];
+                self.positions[M0 + player] = position % VISIBLE_PIXELS;
+            }
+        }
+    }
+
+    // Stores a data register; strobes and unused addresses hold nothing
+    fn latch(&mut self, address: u16, data: u8) {
+        let registers = &mut self.registers;
+
+        match address {
+            VSYNC  => registers.vsync = data & VSYNC_MASK,
+            VBLANK => registers.vblank = data & VBLANK_MASK,
+            NUSIZ0 => registers.nusiz[0] = data & NUSIZ_MASK,
+            NUSIZ1 => registers.nusiz[1] = data & NUSIZ_MASK,
+            COLUP0 => registers.colup[0] = data & COLOR_MASK,
+            COLUP1 => registers.colup[1] = data & COLOR_MASK,
+            COLUPF => registers.colupf = data & COLOR_MASK,
+            COLUBK => registers.colubk = data & COLOR_MASK,
+            CTRLPF => registers.ctrlpf = data & CTRLPF_MASK,
+            REFP0  => registers.refp[0] = data & REFP_MASK,
+            REFP1  => registers.refp[1] = data & REFP_MASK,
+            PF0    => registers.pf[0] = data & PF0_MASK,
+            PF1    => registers.pf[1] = data,
+            PF2    => registers.pf[2] = data,
+            AUDC0  => registers.audc[0] = data & AUDC_MASK,
+            AUDC1  => registers.audc[1] = data & AUDC_MASK,
+            AUDF0  => registers.audf[0] = data & AUDF_MASK,
+            AUDF1  => registers.audf[1] = data & AUDF_MASK,
+            AUDV0  => registers.audv[0] = data & AUDV_MASK,
+            AUDV1  => registers.audv[1] = data & AUDV_MASK,
+            GRP0   => registers.grp[0] = data,
+            GRP1   => registers.grp[1] = data,
+            ENAM0  => registers.enam[0] = data & ENABLE_MASK,
+            ENAM1  => registers.enam[1] = data & ENABLE_MASK,
+            ENABL  => registers.enabl = data & ENABLE_MASK,
+            HMP0   => registers.hmp[0] = data & HM_MASK,
+            HMP1   => registers.hmp[1] = data & HM_MASK,
+            HMM0   => registers.hmm[0] = data & HM_MASK,
+            HMM1   => registers.hmm[1] = data & HM_MASK,
+            HMBL   => registers.hmbl = data & HM_MASK,
+            VDELP0 => registers.vdelp[0] = data & VDEL_MASK,
+            VDELP1 => registers.vdelp[1] = data & VDEL_MASK,
+            VDELBL => registers.vdelbl = data & VDEL_MASK,
+            RESMP0 => registers.resmp[0] = data & RESMP_MASK,
+            RESMP1 => registers.resmp[1] = data & RESMP_MASK,
+            _      => (),
+        }
+    }
+
+    // Positions are where an object's counter comes round to its start.
+    // The counter is stopped during horizontal blank, so an object reset
+    // there starts as the counter is let go at the end of it; the extended
+    // blank after HMOVE then moves it on like everything else
+    fn reset_position(&mut self, object: usize) {
+        let (delay, hblank_pixel) = match object {
+            P0 | P1 => (PLAYER_RESET_DELAY, PLAYER_HBLANK_PIXEL),
+            _       => (OBJECT_RESET_DELAY, OBJECT_HBLANK_PIXEL),
+        };
+        let color_clock = self.beam.color_clock;
+
+        self.positions[object] = if color_clock < HBLANK_CLOCKS {
+            hblank_pixel
+        } else if self.in_hmove_blank() {
+            color_clock - HBLANK_CLOCKS + hblank_pixel
+        } else {
+            (color_clock - HBLANK_CLOCKS + delay) % VISIBLE_PIXELS
+        };
+    }
+
+    // Starts the ripple counter that sends objects extra clocks. Each
+    // object stops once the counter matches its motion register, a signed
+    // value in D7-D4 with the sign flipped, so the 8 extra clocks of a 0
+    // make up for the 8 lost to the extended blank
+    fn hmove(&mut self) {
+        self.hmove_delay = HMOVE_DELAY;
+        self.hmove_counter = 0;
+        self.moving = [true; OBJECT_COUNT];
+
+        // a late HMOVE moves objects in the next line's blank instead
+        if self.beam.color_clock < HBLANK_CLOCKS {
+            self.hmove_blank = true;
+        }
+    }
+
+    // Runs one step of an HMOVE in progress. Extra clocks only move an
+    // object while its counter would otherwise be stopped, in horizontal
+    // blank. The motion registers are compared as they are now, so one
+    // changed to a value the counter has gone past never stops moving,
+    // which is where Cosmic Ark's starfield comes from
+    fn hmove_pulse(&mut self) {
+        let registers = &self.registers;
+        let motions = [registers.hmp[0], registers.hmp[1], registers.hmm[0], registers.hmm[1], registers.hmbl];
+        let counter = if self.hmove_counter > HMOVE_COUNTER_MAX { 0 } else { self.hmove_counter };
+        let blank = self.beam.color_clock < HBLANK_CLOCKS || self.in_hmove_blank();
+
+        for ((moving, position), motion) in self.moving.iter_mut().zip(self.positions.iter_mut()).zip(motions.iter()) {
+            if counter == (motion >> 4) ^ 0x08 {
+                *moving = false;
+            }
+
+            if *moving && blank {
+                *position = position.checked_sub(1).unwrap_or(VISIBLE_PIXELS - 1);
+            }
+        }
+
+        self.hmove_counter = self.hmove_counter.saturating_add(1);
+    }
+
+    // The extra pixels of horizontal blank after an HMOVE
+    fn in_hmove_blank(&self) -> bool {
+        self.hmove_blank && (HBLANK_CLOCKS..HBLANK_CLOCKS + HMOVE_BLANK_CLOCKS).contains(&self.beam.color_clock)
+    }
+
+    fn new_line(&mut self) {
+        // the start of horizontal blank releases WSYNC
+        self.beam.color_clock = 0;
+        self.beam.scanline = self.beam.scanline.wrapping_add(1);
+        self.wsync = false;
+    }
+
+    // Advances the beam by the given number of color clocks
+    pub fn tick(&mut self, color_clocks: u16) {
+        for _ in 0..color_clocks {
+            if self.hmove_delay > 0 {
+                self.hmove_delay -= 1;
+            } else if self.moving.iter().any(|moving| *moving) && (self.beam.color_clock & HMOVE_PULSE_MASK) == 0 {
+                self.hmove_pulse();
+                self.lock_missiles();
+            }
+
+            if self.in_hmove_blank() {
+                self.blank(self.beam.color_clock - HBLANK_CLOCKS);
+                self.lock_missiles();
+            } else if self.beam.color_clock >= HBLANK_CLOCKS {
+                self.draw(self.beam.color_clock - HBLANK_CLOCKS);
+            }
+
+            self.charge = if (self.registers.vblank & DUMP_PADDLES_MASK) != 0 {
+                0
+            } else {
+                self.charge.saturating_add(1)
+            };
+
+            self.beam.color_clock += 1;
+
+            if self.beam.color_clock == HBLANK_CLOCKS + HMOVE_BLANK_CLOCKS {
+                self.hmove_blank = false;
+            }
+            if self.beam.color_clock == COLOR_CLOCKS_PER_LINE {
+                self.new_line();
+            }
+        }
+    }
+
+    // The TIA drives the CPU's RDY line, holding it low from a write to
+    // WSYNC until the start of the next scanline
+    pub fn rdy(&self) -> bool {
+        !self.wsync
+    }
+
+    // Horizontal beam position, where 0-67 is horizontal blank
+    pub fn color_clock(&self) -> u8 {
+        self.beam.color_clock
+    }
+
+    pub fn beam(&self) -> Beam {
+        self.beam
+    }
+
+    // The bits latched by a data register, decoded from A0-A5. Strobes and
+    // unused addresses read as 0
+    pub fn register(&self, address: u16) -> u8 {
+        let registers = &self.registers;
+
+        match address & 0x3F {
+            VSYNC  => registers.vsync,
+            VBLANK => registers.vblank,
+            NUSIZ0 => registers.nusiz[0],
+            NUSIZ1 => registers.nusiz[1],
+            COLUP0 => registers.colup[0],
+            COLUP1 => registers.colup[1],
+            COLUPF => registers.colupf,
+            COLUBK => registers.colubk,
+            CTRLPF => registers.ctrlpf,
+            REFP0  => registers.refp[0],
+            REFP1  => registers.refp[1],
+            PF0    => registers.pf[0],
+            PF1    => registers.pf[1],
+            PF2    => registers.pf[2],
+            AUDC0  => registers.audc[0],
+            AUDC1  => registers.audc[1],
+            AUDF0  => registers.audf[0],
+            AUDF1  => registers.audf[1],
+            AUDV0  => registers.audv[0],
+            AUDV1  => registers.audv[1],
+            GRP0   => registers.grp[0],
+            GRP1   => registers.grp[1],
+            ENAM0  => registers.enam[0],
+            ENAM1  => registers.enam[1],
+            ENABL  => registers.enabl,
+            HMP0   => registers.hmp[0],
+            HMP1   => registers.hmp[1],
+            HMM0   => registers.hmm[0],
+            HMM1   => registers.hmm[1],
+            HMBL   => registers.hmbl,
+            VDELP0 => registers.vdelp[0],
+            VDELP1 => registers.vdelp[1],
+            VDELBL => registers.vdelbl,
+            RESMP0 => registers.resmp[0],
+            RESMP1 => registers.resmp[1],
+            _      => 0,
+        }
+    }
+
+    // Horizontal positions as visible pixels, indexed by P0, P1, M0, M1
+    // and BL
+    pub fn positions(&self) -> [u8; 5] {
+        self.positions
+    }
+
+    pub fn save_state<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        for address in 0..REGISTER_COUNT {
+            state::write_u8(out, self.register(address))?;
+        }
+        state::write_u8(out, self.old_grp[0])?;
+        state::write_u8(out, self.old_grp[1])?;
+        state::write_u8(out, self.old_enabl)?;
+        for position in &self.positions {
+            state::write_u8(out, *position)?;
+        }
+        state::write_u16(out, self.collisions)?;
+        state::write_u8(out, self.hmove_delay)?;
+        state::write_u8(out, self.hmove_counter)?;
+        for moving in &self.moving {
+            state::write_bool(out, *moving)?;
+        }
+        state::write_bool(out, self.hmove_blank)?;
+        for paddle in &self.paddles {
+            state::write_u32(out, paddle.unwrap_or(u32::MAX))?;
+        }
+        state::write_u32(out, self.charge)?;
+        for button in 0..2 {
+            state::write_bool(out, self.fire_buttons[button])?;
+            state::write_bool(out, self.fire_latches[button])?;
+        }
+        state::write_u32(out, self.beam.frame)?;
+        state::write_u16(out, self.beam.scanline)?;
+        state::write_u8(out, self.beam.color_clock)?;
+        state::write_bool(out, self.wsync)
+    }
+
+    pub fn load_state<R: Read>(&mut self, input: &mut R) -> io::Result<()> {
+        for address in 0..REGISTER_COUNT {
+            let data = state::read_u8(input)?;
+            self.latch(address, data);
+        }
+        self.old_grp[0] = state::read_u8(input)?;
+        self.old_grp[1] = state::read_u8(input)?;
+        self.old_enabl = state::read_u8(input)?;
+        for position in self.positions.iter_mut() {
+            *position = state::read_u8(input)?;
+        }
+        self.collisions = state::read_u16(input)?;
+        self.hmove_delay = state::read_u8(input)?;
+        self.hmove_counter = state::read_u8(input)?;
+        for moving in self.moving.iter_mut() {
+            *moving = state::read_bool(input)?;
+        }
+        self.hmove_blank = state::read_bool(input)?;
+        for paddle in self.paddles.iter_mut() {
+            *paddle = Some(state::read_u32(input)?).filter(|time| *time != u32::MAX);
+        }
+        self.charge = state::read_u32(input)?;
+        for button in 0..2 {
+            self.fire_buttons[button] = state::read_bool(input)?;
+            self.fire_latches[button] = state::read_bool(input)?;
+        }
+        self.beam.frame = state::read_u32(input)?;
+        self.beam.scanline = state::read_u16(input)?;
+        self.beam.color_clock = state::read_u8(input)?;
+        self.wsync = state::read_bool(input)?;
+
+        if self.beam.color_clock >= COLOR_CLOCKS_PER_LINE {
+            return Err(state::invalid("beam past the end of the line"));
+        }
+        if self.positions.iter().any(|position| *position >= VISIBLE_PIXELS) {
+            return Err(state::invalid("object past the end of the line"));
         }
+        Ok(())
+    }
+
+    // The colors drawn so far this frame, FRAME_WIDTH to a scanline, as
+    // the luminance and hue written to the color registers. Lines past
+    // FRAME_HEIGHT aren't kept
+    pub fn frame_buffer(&self) -> &[u8] {
+        &self.frame_buffer
+    }
+
+    // A pixel of the extended blank is black, and every object's counter
+    // misses its clock, pushing it a pixel to the right
+    fn blank(&mut self, pixel: u8) {
+        for position in self.positions.iter_mut() {
+            *position = (*position + 1) % VISIBLE_PIXELS;
+        }
+
+        let line = self.beam.scanline as usize;
+        if line < FRAME_HEIGHT {
+            self.frame_buffer[line * FRAME_WIDTH + pixel as usize] = 0;
+        }
+    }
+
+    fn draw(&mut self, pixel: u8) {
+        let objects = self.objects(pixel);
+        self.collisions |= COLLISIONS[objects as usize];
+
+        let line = self.beam.scanline as usize;
+        if line < FRAME_HEIGHT {
+            let color = if (self.registers.vblank & BLANK_MASK) != 0 { 0 } else { self.color(objects, pixel) };
+            self.frame_buffer[line * FRAME_WIDTH + pixel as usize] = color;
+        }
+    }
+
+    // Players and their missiles normally cover the playfield and ball,
+    // with player 0 on top; CTRLPF's priority bit puts the playfield and
+    // ball over both. Score mode draws each half of the playfield in its
+    // player's color, unless priority is set
+    fn color(&self, objects: u8, pixel: u8) -> u8 {
+        let registers = &self.registers;
+        let priority = (registers.ctrlpf & PRIORITY_MASK) != 0;
+
+        let playfield = if (objects & (1 << BL)) != 0 {
+            Some(registers.colupf)
+        } else if (objects & (1 << PF)) != 0 {
+            if (registers.ctrlpf & SCORE_MASK) != 0 && !priority {
+                Some(registers.colup[(pixel >= VISIBLE_PIXELS / 2) as usize])
+            } else {
+                Some(registers.colupf)
+            }
+        } else {
+            None
+        };
+
+        let player = if (objects & (1 << P0 | 1 << M0)) != 0 {
+            Some(registers.colup[0])
+        } else if (objects & (1 << P1 | 1 << M1)) != 0 {
+            Some(registers.colup[1])
+        } else {
+            None
+        };
+
+        let top = if priority { playfield.or(player) } else { player.or(playfield) };
+        top.unwrap_or(registers.colubk)
+    }
+
+    // The set of objects drawing the given visible pixel, as bits indexed
+    // by P0 through PF
+    fn objects(&self, pixel: u8) -> u8 {
+        let registers = &self.registers;
+        let mut objects = 0;
+
+        for player in 0..2 {
+            let mode = (registers.nusiz[player] & COPIES_MASK) as usize;
+            let copies = NUSIZ_COPIES[mode];
+
+            let graphics = if registers.vdelp[player] != 0 { self.old_grp[player] } else { registers.grp[player] };
+            if graphics != 0 {
+                let scale = NUSIZ_SCALE[mode];
+                let delay = (scale > 1) as u8;
+                let offset = self.offset(P0 + player, pixel);
+
+                for copy in copies {
+                    let start = copy + delay;
+                    if offset >= start && offset - start < 8 * scale {
+                        let index = (offset - start) / scale;
+                        let bit = if registers.refp[player] != 0 { index } else { 7 - index };
+                        objects |= ((graphics >> bit) & 1) << (P0 + player);
+                    }
+                }
+            }
+
+            if registers.enam[player] != 0 && registers.resmp[player] == 0 {
+                let width = 1 << ((registers.nusiz[player] >> 4) & 0x03);
+                let offset = self.offset(M0 + player, pixel);
+
+                if copies.iter().any(|copy| offset >= *copy && offset - copy < width) {
+                    objects |= 1 << (M0 + player);
+                }
+            }
+        }
+
+        let enabl = if registers.vdelbl != 0 { self.old_enabl } else { registers.enabl };
+        let ball_size = 1 << ((registers.ctrlpf >> 4) & 0x03);
+        if enabl != 0 && self.offset(BL, pixel) < ball_size {
+            objects |= 1 << BL;
+        }
+
+        if self.playfield(pixel) {
+            objects |= 1 << PF;
+        }
+
+        objects
+    }
+
+    // How far the pixel is to the right of an object's position
+    fn offset(&self, object: usize, pixel: u8) -> u8 {
+        if pixel >= self.positions[object] {
+            pixel - self.positions[object]
+        } else {
+            VISIBLE_PIXELS - (self.positions[object] - pixel)
+        }
+    }
+
+    // The playfield is 20 bits, 4 pixels each, drawn from PF0 D4-D7, PF1
+    // D7-D0 and PF2 D0-D7. The right half repeats them, or mirrors them
+    // when CTRLPF D0 is set
+    fn playfield(&self, pixel: u8) -> bool {
+        let mut bit = pixel / 4;
+        if bit >= 20 {
+            bit = if (self.registers.ctrlpf & REFLECT_MASK) != 0 { 39 - bit } else { bit - 20 };
+        }
+
+        let pf = &self.registers.pf;
+        let set = match bit {
+            0..=3  => pf[0] >> (bit + 4),
+            4..=11 => pf[1] >> (11 - bit),
+            _      => pf[2] >> (bit - 12),
+        };
+        (set & 1) != 0
+    }
+
+    fn vsync(&self) -> bool {
+        self.registers.vsync != 0
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    
+
     #[test]
     fn it_works() {
         assert_eq!(4, 4);
     }
+
+    #[test]
+    fn wsync() {
+        let mut tia = Tia1A::new();
+        tia.tick(10);
+
+        tia.write(0x42, 0); // WSYNC mirror
+
+        assert!(!tia.rdy());
+
+        tia.tick(217);
+
+        assert!(!tia.rdy());
+
+        tia.tick(1);
+
+        assert!(tia.rdy());
+        assert_eq!(tia.color_clock(), 0);
+    }
+
+    #[test]
+    fn beam() {
+        let mut tia = Tia1A::new();
+        tia.tick(228 * 3 + 5);
+
+        assert_eq!(tia.beam(), Beam { frame: 0, scanline: 3, color_clock: 5 });
+
+        tia.write(0x00, 0x02); // VSYNC on
+        tia.tick(228);
+        tia.write(0x00, 0x02);
+
+        // only turning VSYNC on starts a frame
+        assert_eq!(tia.beam(), Beam { frame: 1, scanline: 1, color_clock: 5 });
+    }
+
+    #[test]
+    fn masks_unused_bits() {
+        let mut tia = Tia1A::new();
+        tia.write(0x0D, 0xFF); // PF0
+        tia.write(0x04, 0xFF); // NUSIZ0
+        tia.write(0x49, 0xFF); // COLUBK mirror
+        tia.write(0x2D, 0xFF); // unused
+
+        assert_eq!(tia.register(0x0D), 0xF0);
+        assert_eq!(tia.register(0x04), 0x37);
+        assert_eq!(tia.register(0x09), 0xFE);
+        assert_eq!(tia.register(0x2D), 0);
+    }
+
+    #[test]
+    fn vertical_delay() {
+        let mut tia = Tia1A::new();
+        tia.write(0x1F, 0x02); // ENABL
+        tia.write(0x1B, 0xAA); // GRP0
+        tia.write(0x1C, 0x55); // GRP1 copies GRP0 and ENABL
+        tia.write(0x1B, 0x11); // GRP0 copies GRP1
+
+        assert_eq!(tia.old_grp, [0xAA, 0x55]);
+        assert_eq!(tia.old_enabl, 0x02);
+        assert_eq!(tia.register(0x1B), 0x11);
+    }
+
+    #[test]
+    fn position_strobes() {
+        let mut tia = Tia1A::new();
+        tia.write(0x10, 0); // RESP0 in horizontal blank
+        tia.write(0x12, 0); // RESM0
+        tia.tick(100);
+        tia.write(0x11, 0); // RESP1
+        tia.write(0x14, 0); // RESBL
+
+        // players start 5 pixels after the write, other objects 4
+        assert_eq!(tia.positions(), [3, 37, 2, 0, 36]);
+
+        tia.tick(127);
+        tia.write(0x13, 0); // RESM1 on the last pixel, wrapping round
+
+        assert_eq!(tia.positions()[M1], 3);
+
+        tia.write(0x20, 0x70); // HMP0
+        tia.write(0x2B, 0);    // HMCLR
+
+        assert_eq!(tia.register(0x20), 0);
+    }
+
+    // Runs HMOVE at a color clock on a line with every object at 50 and
+    // returns where they end up after it
+    fn hmove_at(color_clock: u16, motion: u8) -> Tia1A {
+        let mut tia = Tia1A::new();
+        tia.positions = [50; 5];
+        for address in 0x20..0x25 {
+            tia.write(address, motion);
+        }
+        tia.tick(color_clock);
+        tia.write(0x2A, 0);
+        tia.tick(228 * 2 - color_clock);
+        tia
+    }
+
+    #[test]
+    fn hmove() {
+        assert_eq!(hmove_at(9, 0x70).positions(), [43; 5]);
+        assert_eq!(hmove_at(9, 0x00).positions(), [50; 5]);
+        assert_eq!(hmove_at(9, 0x80).positions(), [58; 5]);
+
+        // the comb, whatever the background
+        let mut tia = hmove_at(9, 0x00);
+        tia.write(0x09, 0x44); // COLUBK
+        tia.write(0x2A, 0);
+        tia.tick(228);
+
+        assert_eq!(&line(&tia, 2)[..9], &[0, 0, 0, 0, 0, 0, 0, 0, 0x44]);
+        assert_eq!(tia.positions(), [50; 5]);
+    }
+
+    #[test]
+    fn late_hmove() {
+        // at the end of the line there's no comb, and the extra clocks
+        // land in the next line's blank, moving 8 further left
+        let tia = hmove_at(222, 0x30);
+
+        assert_eq!(tia.positions(), [39; 5]);
+        assert!(line(&tia, 1)[..8].iter().all(|color| *color == 0));
+
+        // midway along, the counter finishes while the objects are
+        // already being clocked
+        assert_eq!(hmove_at(120, 0x70).positions(), [50; 5]);
+    }
+
+    #[test]
+    fn cosmic_ark_starfield() {
+        let mut tia = Tia1A::new();
+        tia.positions[M0] = 100;
+        tia.write(0x22, 0x70); // HMM0, 7 left
+        tia.write(0x2A, 0);    // HMOVE
+        tia.tick(16);
+        tia.write(0x22, 0x90); // HMM0 changed to 1 after the counter's passed it
+        tia.tick(228 - 16);
+
+        // 17 extra clocks through the blank and comb, less the 8 missed
+        assert_eq!(tia.positions()[M0], 91);
+
+        // and then it keeps moving 17 pixels every line
+        tia.tick(228);
+
+        assert_eq!(tia.positions()[M0], 74);
+    }
+
+    #[test]
+    fn rsync() {
+        let mut tia = Tia1A::new();
+        tia.tick(100);
+        tia.write(0x02, 0); // WSYNC
+        tia.write(0x03, 0); // RSYNC
+
+        assert_eq!(tia.beam(), Beam { frame: 0, scanline: 1, color_clock: 0 });
+        assert!(tia.rdy());
+    }
+
+    #[test]
+    fn collisions() {
+        let mut tia = Tia1A::new();
+        tia.tick(68 + 9);
+        tia.write(0x10, 0);    // RESP0 at 14
+        tia.tick(1);
+        tia.write(0x14, 0);    // RESBL at 14
+        tia.write(0x1B, 0x80); // GRP0, leftmost pixel only
+        tia.write(0x1F, 0x02); // ENABL
+        tia.write(0x0D, 0x10); // PF0, pixels 0-3
+        tia.tick(228);
+
+        assert_eq!(tia.peek(0x02), 0x40); // CXP0FB, P0-BL
+        assert_eq!(tia.peek(0x06), 0);    // CXBLPF
+        assert_eq!(tia.peek(0x07), 0);    // CXPPMM
+
+        tia.write(0x2C, 0);    // CXCLR
+        tia.write(0x0A, 0x30); // CTRLPF, 8 pixel ball
+        tia.write(0x0D, 0xF0); // PF0, pixels 0-15
+        tia.tick(228);
+
+        assert_eq!(tia.peek(0x02), 0xC0);
+        assert_eq!(tia.peek(0x06), 0x80);
+        assert_eq!(tia.peek(0x14), 0);    // CXM0FB mirror
+
+        tia.write(0x2C, 0);
+
+        assert_eq!(tia.peek(0x02), 0);
+        assert_eq!(tia.peek(0x06), 0);
+    }
+
+    #[test]
+    fn playfield() {
+        let mut tia = Tia1A::new();
+        tia.write(0x0D, 0x10); // PF0 D4, bit 0
+        tia.write(0x0E, 0x01); // PF1 D0, bit 11
+        tia.write(0x0F, 0x80); // PF2 D7, bit 19
+
+        let pixels = [0, 44, 76, 80, 124, 156, 4, 112];
+
+        assert_eq!(pixels.iter().map(|pixel| tia.playfield(*pixel)).collect::<Vec<bool>>(),
+                   vec![true, true, true, true, true, true, false, false]);
+
+        tia.write(0x0A, 0x01); // CTRLPF, reflected
+
+        assert_eq!(pixels.iter().map(|pixel| tia.playfield(*pixel)).collect::<Vec<bool>>(),
+                   vec![true, true, true, true, false, true, false, true]);
+    }
+
+    #[test]
+    fn paddles() {
+        let mut tia = Tia1A::new();
+        tia.set_paddle(1, Some(2));
+        tia.write(0x01, 0x80); // VBLANK, dump
+        tia.tick(228 * 4);
+
+        assert_eq!(tia.peek(0x09), 0);
+
+        tia.write(0x01, 0x00);
+        tia.tick(228 * 2 - 1);
+
+        assert_eq!(tia.peek(0x09), 0);
+
+        tia.tick(1);
+
+        assert_eq!(tia.peek(0x09), 0x80);
+        assert_eq!(tia.peek(0x08), 0); // nothing plugged in
+    }
+
+    #[test]
+    fn fire_buttons() {
+        let mut tia = Tia1A::new();
+
+        assert_eq!(tia.peek(0x0C), 0x80);
+
+        tia.set_fire_button(0, true);
+
+        assert_eq!(tia.peek(0x0C), 0);
+
+        tia.set_fire_button(0, false);
+        tia.write(0x01, 0x40); // VBLANK, latch
+        tia.set_fire_button(0, true);
+        tia.set_fire_button(0, false);
+
+        assert_eq!(tia.peek(0x0C), 0);
+        assert_eq!(tia.peek(0x0D), 0x80);
+
+        tia.write(0x01, 0x00);
+
+        assert_eq!(tia.peek(0x0C), 0x80);
+    }
+
+    fn line(tia: &Tia1A, scanline: usize) -> &[u8] {
+        &tia.frame_buffer()[scanline * FRAME_WIDTH..(scanline + 1) * FRAME_WIDTH]
+    }
+
+    #[test]
+    fn draws_playfield() {
+        let mut tia = Tia1A::new();
+        tia.write(0x08, 0x1F); // COLUPF, D0 unused
+        tia.write(0x09, 0x80); // COLUBK
+        tia.write(0x0D, 0x30); // PF0 D4-D5, pixels 0-7
+        tia.tick(228);
+
+        assert_eq!(&line(&tia, 0)[..9], &[0x1E, 0x1E, 0x1E, 0x1E, 0x1E, 0x1E, 0x1E, 0x1E, 0x80]);
+        assert_eq!(line(&tia, 0)[80], 0x1E);
+
+        tia.write(0x01, 0x02); // VBLANK
+        tia.tick(228);
+
+        assert!(line(&tia, 1).iter().all(|color| *color == 0));
+    }
+
+    #[test]
+    fn score_and_priority() {
+        let mut tia = Tia1A::new();
+        tia.write(0x06, 0x40); // COLUP0
+        tia.write(0x07, 0x60); // COLUP1
+        tia.write(0x08, 0x20); // COLUPF
+        tia.write(0x0D, 0xF0); // PF0, pixels 0-15 and 80-95
+        tia.write(0x0A, 0x02); // CTRLPF, score
+        tia.tick(68 + 3);
+        tia.write(0x10, 0);    // RESP0 at 8
+        tia.write(0x1B, 0xFF); // GRP0
+        tia.tick(228 - 68 - 3 + 228);
+
+        assert_eq!((line(&tia, 1)[0], line(&tia, 1)[8], line(&tia, 1)[80]), (0x40, 0x40, 0x60));
+
+        tia.write(0x0A, 0x06); // CTRLPF, score and priority
+        tia.write(0x0D, 0x00);
+        tia.write(0x0E, 0x80); // PF1 D7, pixels 16-19
+        tia.write(0x1F, 0x02); // ENABL, with the ball at 0
+        tia.tick(228);
+
+        assert_eq!((line(&tia, 2)[0], line(&tia, 2)[8], line(&tia, 2)[16]), (0x20, 0x40, 0x20));
+
+        tia.write(0x0A, 0x30); // CTRLPF, 8 pixel ball
+        tia.write(0x10, 0);    // RESP0 in horizontal blank, at 3
+        tia.tick(228);
+
+        assert_eq!((line(&tia, 3)[0], line(&tia, 3)[4], line(&tia, 3)[16]), (0x20, 0x40, 0x20));
+    }
+
+    // the pixels an object covers on a line, with everything else off
+    fn pixels(tia: &Tia1A, object: usize) -> Vec<u8> {
+        (0..160).filter(|pixel| (tia.objects(*pixel) & (1 << object)) != 0).collect()
+    }
+
+    #[test]
+    fn nusiz_players() {
+        let mut tia = Tia1A::new();
+        tia.write(0x1B, 0x80); // GRP0, leftmost pixel only
+
+        let expected: [&[u8]; 8] = [
+            &[0], &[0, 16], &[0, 32], &[0, 16, 32], &[0, 64], &[1, 2], &[0, 32, 64], &[1, 2, 3, 4],
+        ];
+
+        for (mode, pixels_on) in expected.iter().enumerate() {
+            tia.write(0x04, mode as u8);
+            assert_eq!(pixels(&tia, P0), pixels_on.to_vec(), "NUSIZ0 {}", mode);
+        }
+
+        // copies wrap around the end of the line
+        tia.positions[P0] = 150;
+        tia.write(0x04, 0x01);
+
+        assert_eq!(pixels(&tia, P0), vec![6, 150]);
+    }
+
+    #[test]
+    fn reflected_player() {
+        let mut tia = Tia1A::new();
+        tia.positions[P1] = 10;
+        tia.write(0x1C, 0x03); // GRP1
+        tia.write(0x0C, 0x08); // REFP1
+
+        assert_eq!(pixels(&tia, P1), vec![10, 11]);
+
+        tia.write(0x05, 0x05); // NUSIZ1, double
+
+        assert_eq!(pixels(&tia, P1), vec![11, 12, 13, 14]);
+
+        tia.write(0x0C, 0x00);
+
+        assert_eq!(pixels(&tia, P1), vec![23, 24, 25, 26]);
+    }
+
+    #[test]
+    fn nusiz_missiles() {
+        let mut tia = Tia1A::new();
+        tia.write(0x1D, 0x02); // ENAM0
+        tia.write(0x04, 0x23); // NUSIZ0, 4 pixels, three copies close
+
+        assert_eq!(pixels(&tia, M0), vec![0, 1, 2, 3, 16, 17, 18, 19, 32, 33, 34, 35]);
+
+        tia.write(0x04, 0x17); // 2 pixels, quad player
+
+        assert_eq!(pixels(&tia, M0), vec![0, 1]);
+
+        tia.write(0x04, 0x30); // 8 pixels
+
+        assert_eq!(pixels(&tia, M0), (0..8).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn missiles_locked_to_players() {
+        let mut tia = Tia1A::new();
+        tia.write(0x1E, 0x02); // ENAM1
+        tia.positions[P1] = 20;
+        tia.write(0x29, 0x02); // RESMP1
+
+        assert_eq!(tia.positions()[M1], 23);
+        assert_eq!(pixels(&tia, M1), vec![]);
+
+        tia.write(0x05, 0x07); // NUSIZ1, quad
+
+        assert_eq!(tia.positions()[M1], 30);
+
+        tia.write(0x29, 0x00);
+        tia.positions[P1] = 40;
+
+        assert_eq!(pixels(&tia, M1), vec![30]);
+    }
+
+    #[test]
+    fn locked_missiles_follow_hmove() {
+        let mut tia = Tia1A::new();
+        tia.positions[P0] = 50;
+        tia.write(0x28, 0x02); // RESMP0
+        tia.write(0x20, 0x70); // HMP0, 7 left
+        tia.write(0x22, 0x80); // HMM0, 8 right, which the lock overrides
+        tia.write(0x2A, 0);    // HMOVE
+        tia.tick(228);
+
+        assert_eq!(tia.positions()[P0], 43);
+        assert_eq!(tia.positions()[M0], 46);
+
+        tia.write(0x28, 0x00);
+        tia.tick(228);
+
+        assert_eq!(tia.positions()[M0], 46);
+    }
 }
@@ -0,0 +1,165 @@
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use bus::Bus;
+use cpu::Mos6507;
+use disasm::Instruction;
+
+// the listing is padded to this width before the registers, as in nestest
+const LISTING_WIDTH: usize  = 48;
+
+// Writes a line for each instruction the CPU is about to execute, close
+// to the format of Stella's and nestest's traces:
+//
+// F000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:24 SP:FD CYC:7 SL:0 CLK:21
+pub struct Tracer {
+    out: Box<dyn Write>,
+    addresses: RangeInclusive<u16>,
+    frames: RangeInclusive<u32>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(out: W) -> Tracer {
+        Tracer {
+            out: Box::new(out),
+            addresses: 0x0000..=0xFFFF,
+            frames: 0..=u32::MAX,
+        }
+    }
+
+    // Only trace instructions whose address is in the range
+    pub fn set_address_range(&mut self, addresses: RangeInclusive<u16>) {
+        self.addresses = addresses;
+    }
+
+    // Only trace instructions in the range of frames, as counted by the TIA
+    pub fn set_frame_range(&mut self, frames: RangeInclusive<u32>) {
+        self.frames = frames;
+    }
+
+    pub fn trace<B: Bus>(&mut self, cpu: &Mos6507, bus: &B) -> io::Result<()> {
+        let pc = cpu.pc();
+        let beam = bus.beam();
+
+        if !self.addresses.contains(&pc) || !self.frames.contains(&beam.frame) {
+            return Ok(());
+        }
+
+        writeln!(self.out, "{}", line(cpu, bus))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+// Formats one line of the trace for the instruction at PC
+pub fn line<B: Bus>(cpu: &Mos6507, bus: &B) -> String {
+    let pc = cpu.pc();
+    let beam = bus.beam();
+
+    let bytes = [bus.peek(pc), bus.peek(pc.wrapping_add(1)), bus.peek(pc.wrapping_add(2))];
+    let listing = Instruction::decode(&bytes, pc).unwrap().listing();
+
+    format!("{:<width$}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{} SL:{} CLK:{}",
+            listing, cpu.a(), cpu.x(), cpu.y(), cpu.flags(), cpu.sp(), cpu.cycles(),
+            beam.scanline, beam.color_clock, width = LISTING_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use bus::FlatRam;
+
+    // Lets the test read back what the tracer wrote
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(data)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.borrow().clone()).unwrap()
+                                                      .lines()
+                                                      .map(String::from)
+                                                      .collect()
+        }
+    }
+
+    fn traced_cpu(code: &[u8]) -> (Mos6507, FlatRam, SharedBuffer) {
+        let mut ram = FlatRam::new();
+        ram.load(0xF000, code);
+        ram.load(0xFFFC, &[0x00, 0xF0]);
+
+        let mut cpu = Mos6507::new();
+        cpu.reset(&mut ram);
+
+        let buffer = SharedBuffer::default();
+        cpu.set_tracer(Some(Tracer::new(buffer.clone())));
+
+        (cpu, ram, buffer)
+    }
+
+    #[test]
+    fn format() {
+        // LDA #$42, SLO $80
+        let (mut cpu, mut ram, buffer) = traced_cpu(&[0xA9, 0x42, 0x07, 0x80]);
+
+        cpu.step(&mut ram);
+        cpu.step(&mut ram);
+
+        assert_eq!(buffer.lines(), vec![
+            "F000  A9 42     LDA #$42                        A:00 X:00 Y:00 P:24 SP:FD CYC:7 SL:0 CLK:0",
+            "F002  07 80    *SLO $80                         A:42 X:00 Y:00 P:24 SP:FD CYC:9 SL:0 CLK:0",
+        ]);
+    }
+
+    #[test]
+    fn tick_traces_once() {
+        let (mut cpu, mut ram, buffer) = traced_cpu(&[0xEE, 0x80, 0x00, 0xEA]); // INC $0080, NOP
+
+        while !cpu.tick(&mut ram) {}
+        cpu.tick(&mut ram);
+
+        assert_eq!(buffer.lines().len(), 2);
+        assert!(buffer.lines()[1].contains("CYC:13"));
+    }
+
+    #[test]
+    fn address_range() {
+        let (mut cpu, mut ram, buffer) = traced_cpu(&[0xEA, 0xEA, 0xEA]);
+        let mut tracer = Tracer::new(buffer.clone());
+        tracer.set_address_range(0xF001..=0xF001);
+        cpu.set_tracer(Some(tracer));
+
+        for _ in 0..3 {
+            cpu.step(&mut ram);
+        }
+
+        assert_eq!(buffer.lines().len(), 1);
+        assert!(buffer.lines()[0].starts_with("F001"));
+    }
+
+    #[test]
+    fn frame_range() {
+        let (mut cpu, mut ram, buffer) = traced_cpu(&[0xEA]);
+        let mut tracer = Tracer::new(buffer.clone());
+        tracer.set_frame_range(1..=2);
+        cpu.set_tracer(Some(tracer));
+
+        // a flat RAM bus has no TIA, so it never leaves frame 0
+        cpu.step(&mut ram);
+
+        assert!(buffer.lines().is_empty());
+    }
+}
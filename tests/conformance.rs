@@ -0,0 +1,325 @@
+// Runs the CPU against the standard 6502 conformance suites; see
+// tests/data/README.md for where their data comes from. A test fails
+// when its data is missing. The ProcessorTests vectors are too big to
+// keep in the repository, so that test only runs with --ignored.
+
+extern crate twenty_six;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use twenty_six::bus::{Bus, FlatRam};
+use twenty_six::cpu::{Mos6507, OPCODES};
+
+const DATA_DIR: &str                = "tests/data";
+
+// where the default build of 6502_functional_test.bin starts and where
+// it traps once every test has passed
+const FUNCTIONAL_TEST: &str         = "6502_functional_test.bin";
+const FUNCTIONAL_START: u16         = 0x0400;
+const FUNCTIONAL_SUCCESS: u16       = 0x3469;
+
+// 6502_decimal_test.bin is assembled at $0200 and leaves 0 in ERROR
+const DECIMAL_TEST: &str            = "6502_decimal_test.bin";
+const DECIMAL_START: u16            = 0x0200;
+const DECIMAL_ERROR: u16            = 0x000B;
+// the end_of_test macro stops with a 65C02 STP
+const STP: u8                       = 0xDB;
+
+const PROCESSOR_TESTS: &str         = "ProcessorTests/6502/v1";
+
+// generous upper bound; the functional test takes about 30 million
+const INSTRUCTION_LIMIT: u64        = 100_000_000;
+
+// the flags that aren't stored in the status register
+const BREAK_UNUSED_MASK: u8         = 0x30;
+
+fn read_data(name: &str) -> Vec<u8> {
+    let path = Path::new(DATA_DIR).join(name);
+    let mut data = Vec::new();
+
+    File::open(&path).and_then(|mut file| file.read_to_end(&mut data))
+                     .unwrap_or_else(|error| panic!("can't read {} ({}), see {}/README.md", path.display(), error,
+                                                    DATA_DIR));
+    data
+}
+
+// Runs from start until the CPU jumps or branches to itself, or reaches
+// a STP, and returns where it stopped
+fn run_until_trapped(ram: &mut FlatRam, start: u16) -> u16 {
+    let mut cpu = Mos6507::new();
+    cpu.set_pc(start);
+
+    for _ in 0..INSTRUCTION_LIMIT {
+        let pc = cpu.pc();
+
+        if ram.peek(pc) == STP {
+            return pc;
+        }
+
+        cpu.step(ram);
+
+        if cpu.pc() == pc {
+            return pc;
+        }
+    }
+
+    panic!("no trap after {} instructions, at {:04X}", INSTRUCTION_LIMIT, cpu.pc());
+}
+
+#[test]
+fn klaus_dormann_functional() {
+    let binary = read_data(FUNCTIONAL_TEST);
+
+    let mut ram = FlatRam::new();
+    ram.load(0x0000, &binary);
+
+    assert_eq!(run_until_trapped(&mut ram, FUNCTIONAL_START), FUNCTIONAL_SUCCESS);
+}
+
+#[test]
+fn klaus_dormann_decimal() {
+    let binary = read_data(DECIMAL_TEST);
+
+    let mut ram = FlatRam::new();
+    ram.load(DECIMAL_START, &binary);
+    run_until_trapped(&mut ram, DECIMAL_START);
+
+    assert_eq!(ram.peek(DECIMAL_ERROR), 0);
+}
+
+// Just enough JSON for the ProcessorTests vectors
+#[derive(Debug)]
+enum Json {
+    Number(i64),
+    Text(String),
+    Array(Vec<Json>),
+    Object(BTreeMap<String, Json>),
+    Other,
+}
+
+impl Json {
+    fn parse(text: &[u8]) -> Json {
+        let mut position = 0;
+        Json::value(text, &mut position)
+    }
+
+    fn value(text: &[u8], position: &mut usize) -> Json {
+        Json::skip_whitespace(text, position);
+
+        match text[*position] {
+            b'{' => {
+                let mut object = BTreeMap::new();
+                *position += 1;
+
+                loop {
+                    Json::skip_whitespace(text, position);
+                    if text[*position] == b'}' {
+                        *position += 1;
+                        return Json::Object(object);
+                    }
+                    if text[*position] == b',' {
+                        *position += 1;
+                        continue;
+                    }
+
+                    let key = match Json::value(text, position) {
+                        Json::Text(key) => key,
+                        other           => panic!("bad key {:?}", other),
+                    };
+                    Json::skip_whitespace(text, position);
+                    *position += 1; // the colon
+                    object.insert(key, Json::value(text, position));
+                }
+            },
+            b'[' => {
+                let mut array = Vec::new();
+                *position += 1;
+
+                loop {
+                    Json::skip_whitespace(text, position);
+                    match text[*position] {
+                        b']' => {
+                            *position += 1;
+                            return Json::Array(array);
+                        },
+                        b',' => *position += 1,
+                        _    => array.push(Json::value(text, position)),
+                    }
+                }
+            },
+            b'"' => {
+                let start = *position + 1;
+                *position = start;
+                while text[*position] != b'"' {
+                    *position += 1;
+                }
+                *position += 1;
+                Json::Text(String::from_utf8_lossy(&text[start..*position - 1]).into_owned())
+            },
+            b'-' | b'0'..=b'9' => {
+                let start = *position;
+                *position += 1;
+                while text[*position].is_ascii_digit() {
+                    *position += 1;
+                }
+                let number = String::from_utf8_lossy(&text[start..*position]);
+                Json::Number(number.parse().unwrap())
+            },
+            _ => {
+                // true, false and null aren't used by the vectors
+                while text[*position].is_ascii_alphabetic() {
+                    *position += 1;
+                }
+                Json::Other
+            },
+        }
+    }
+
+    fn skip_whitespace(text: &[u8], position: &mut usize) {
+        while text[*position].is_ascii_whitespace() {
+            *position += 1;
+        }
+    }
+
+    fn get(&self, key: &str) -> &Json {
+        match *self {
+            Json::Object(ref object) => &object[key],
+            _                        => panic!("not an object"),
+        }
+    }
+
+    fn number(&self) -> i64 {
+        match *self {
+            Json::Number(number) => number,
+            _                    => panic!("not a number"),
+        }
+    }
+
+    fn text(&self) -> &str {
+        match *self {
+            Json::Text(ref text) => text,
+            _                    => panic!("not a string"),
+        }
+    }
+
+    fn array(&self) -> &[Json] {
+        match *self {
+            Json::Array(ref array) => array,
+            _                      => panic!("not an array"),
+        }
+    }
+}
+
+// Records every access, as the vectors list them
+struct CycleBus {
+    ram: FlatRam,
+    cycles: Vec<(u16, u8, &'static str)>,
+}
+
+impl Bus for CycleBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let data = self.ram.read(address);
+        self.cycles.push((address, data, "read"));
+        data
+    }
+
+    fn write(&mut self, address: u16, data: u8) {
+        self.cycles.push((address, data, "write"));
+        self.ram.write(address, data);
+    }
+}
+
+fn registers(cpu: &Mos6507) -> [u16; 6] {
+    [cpu.pc(), cpu.sp() as u16, cpu.a() as u16, cpu.x() as u16, cpu.y() as u16,
+     (cpu.flags() | BREAK_UNUSED_MASK) as u16]
+}
+
+fn expected_registers(state: &Json) -> [u16; 6] {
+    let register = |name| state.get(name).number() as u16;
+    [register("pc"), register("s"), register("a"), register("x"), register("y"),
+     register("p") | BREAK_UNUSED_MASK as u16]
+}
+
+// Runs one vector, describing the first difference if there is one
+fn run_vector(vector: &Json) -> Result<(), String> {
+    let initial = vector.get("initial");
+    let expected = vector.get("final");
+
+    let mut bus = CycleBus { ram: FlatRam::new(), cycles: Vec::new() };
+    for entry in initial.get("ram").array() {
+        let entry = entry.array();
+        bus.ram.write(entry[0].number() as u16, entry[1].number() as u8);
+    }
+
+    let mut cpu = Mos6507::new();
+    cpu.set_pc(initial.get("pc").number() as u16);
+    cpu.set_sp(initial.get("s").number() as u8);
+    cpu.set_a(initial.get("a").number() as u8);
+    cpu.set_x(initial.get("x").number() as u8);
+    cpu.set_y(initial.get("y").number() as u8);
+    cpu.set_flags(initial.get("p").number() as u8);
+
+    cpu.step(&mut bus);
+
+    if registers(&cpu) != expected_registers(expected) {
+        return Err(format!("registers (pc s a x y p) {:04X?}, expected {:04X?}",
+                           registers(&cpu), expected_registers(expected)));
+    }
+
+    for entry in expected.get("ram").array() {
+        let entry = entry.array();
+        let address = entry[0].number() as u16;
+        let data = bus.ram.peek(address);
+
+        if data != entry[1].number() as u8 {
+            return Err(format!("${:04X} is {:02X}, expected {:02X}", address, data, entry[1].number()));
+        }
+    }
+
+    let cycles = vector.get("cycles").array().iter().map(|cycle| {
+        let cycle = cycle.array();
+        (cycle[0].number() as u16, cycle[1].number() as u8, cycle[2].text().to_string())
+    }).collect::<Vec<(u16, u8, String)>>();
+    let actual = bus.cycles.iter().map(|&(address, data, kind)| (address, data, kind.to_string()))
+                                  .collect::<Vec<(u16, u8, String)>>();
+
+    if actual != cycles {
+        return Err(format!("bus activity {:04X?}, expected {:04X?}", actual, cycles));
+    }
+
+    Ok(())
+}
+
+// cargo test -- --ignored, once the vectors are in place
+#[test]
+#[ignore]
+fn tom_harte_processor_tests() {
+    let mut failures = Vec::new();
+
+    for opcode in OPCODES.iter().enumerate().filter(|&(_, entry)| entry.mnemonic != "KIL").map(|(opcode, _)| opcode) {
+        // a jammed CPU stops making bus accesses rather than repeating
+        // them, so the KIL vectors' cycle lists can't match
+        let data = read_data(&format!("{}/{:02x}.json", PROCESSOR_TESTS, opcode));
+
+        for vector in Json::parse(&data).array() {
+            if let Err(difference) = run_vector(vector) {
+                failures.push(format!("{}: {}", vector.get("name").text(), difference));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "{} failed vectors, first: {}", failures.len(), failures[0]);
+}
+
+#[test]
+fn processor_test_vector() {
+    // the harness itself, on a hand written vector for LDA #$42
+    let vector = Json::parse(br#"{"name": "a9 42 00",
+        "initial": {"pc": 512, "s": 253, "a": 0, "x": 0, "y": 0, "p": 38, "ram": [[512, 169], [513, 66]]},
+        "final": {"pc": 514, "s": 253, "a": 66, "x": 0, "y": 0, "p": 36, "ram": [[512, 169], [513, 66]]},
+        "cycles": [[512, 169, "read"], [513, 66, "read"]]}"#);
+
+    assert_eq!(run_vector(&vector), Ok(()));
+}
@@ -1,8 +1,139 @@
 extern crate twenty_six;
 
 use twenty_six::Atari2600;
+use twenty_six::Cartridge;
+use twenty_six::bus::Bus;
+
+// A machine with a 4K cartridge inserted that runs program from $F000,
+// with NOPs after it. The same as the unit tests' fixture, which
+// integration tests can't reach
+fn atari(program: &[u8]) -> Atari2600 {
+    let mut rom = vec![0xEA; 0x1000];
+    rom[..program.len()].copy_from_slice(program);
+    rom[0x0FFC] = 0x00;
+    rom[0x0FFD] = 0xF0;
+
+    let mut atari_2600 = Atari2600::new();
+    atari_2600.insert_cartridge(Cartridge::new(rom).unwrap());
+    atari_2600
+}
+
+// LDX #$00, loop: INX / STX $80 / JMP loop
+fn counting_atari() -> Atari2600 {
+    atari(&[0xA2, 0x00, 0xE8, 0x86, 0x80, 0x4C, 0x02, 0xF0])
+}
 
 #[test]
-fn test_adc() {
-    assert_eq!(1,1);
+fn warm_reset() {
+    let mut atari_2600 = atari(&[0xA9, 0x42,  // LDA #$42
+                                 0x85, 0x80]); // STA $80
+
+    assert_eq!(atari_2600.cpu().pc(), 0xF000);
+
+    atari_2600.step();
+    atari_2600.step();
+    atari_2600.step();
+
+    assert_eq!(atari_2600.cpu().pc(), 0xF005);
+
+    atari_2600.reset();
+
+    assert_eq!(atari_2600.cpu().pc(), 0xF000);
+    assert_eq!(atari_2600.bus().read(0x0080), 0x42);
+}
+
+#[test]
+fn tick_by_cycle() {
+    let mut atari_2600 = atari(&[0x8D, 0x94, 0x02]); // STA $0294
+
+    assert!(!atari_2600.tick());
+    assert!(!atari_2600.tick());
+    assert!(!atari_2600.tick());
+    assert!(atari_2600.tick());
+
+    // A is still 0, so TIM1T expires on the cycle after the write; ticking
+    // the whole instruction at once would have run it three cycles further
+    assert_eq!(atari_2600.bus().read(0x0284), 0xFF);
+    assert_eq!(atari_2600.cpu().pc(), 0xF003);
+}
+
+#[test]
+fn wsync() {
+    let mut atari_2600 = atari(&[0x85, 0x02]); // STA WSYNC
+
+    // the reset sequence leaves the beam at color clock 21
+    assert_eq!(atari_2600.step(), 3);
+
+    // the NOP can't be fetched until the line ends 198 color clocks later
+    assert_eq!(atari_2600.step(), 66 + 2);
+    assert_eq!(atari_2600.cpu().cycles(), 7 + 3 + 66 + 2);
+    assert_eq!(atari_2600.bus().tia().color_clock(), 6);
+}
+
+#[test]
+fn save_state_round_trip() {
+    let mut atari_2600 = counting_atari();
+
+    for _ in 0..10 {
+        atari_2600.step();
+    }
+    atari_2600.tick();
+    atari_2600.bus().poke(0x0281, 0xF0); // SWACNT
+
+    let mut saved = Vec::new();
+    atari_2600.save_state(&mut saved).unwrap();
+    let (pc, cycles) = (atari_2600.cpu().pc(), atari_2600.cpu().cycles());
+
+    for _ in 0..10 {
+        atari_2600.step();
+    }
+    atari_2600.load_state(&mut &saved[..]).unwrap();
+
+    assert_eq!(atari_2600.cpu().pc(), pc);
+    assert_eq!(atari_2600.cpu().cycles(), cycles);
+    assert_eq!(atari_2600.bus().peek(0x0080), 3);
+    assert_eq!(atari_2600.bus().peek(0x0281), 0xF0);
+
+    // the partly ticked instruction picks up where it left off
+    let mut resaved = Vec::new();
+    atari_2600.save_state(&mut resaved).unwrap();
+
+    assert_eq!(resaved, saved);
+}
+
+#[test]
+fn load_state_rejects_other_cartridges() {
+    let mut atari_2600 = counting_atari();
+
+    let mut saved = Vec::new();
+    atari_2600.save_state(&mut saved).unwrap();
+
+    let mut other = Atari2600::new();
+    other.insert_cartridge(Cartridge::new(vec![0; 0x1000]).unwrap());
+
+    assert!(other.load_state(&mut &saved[..]).is_err());
+    assert!(atari_2600.load_state(&mut &saved[4..]).is_err());
+}
+
+#[test]
+fn mid_line_playfield_write() {
+    let mut atari_2600 = atari(&[0xA9, 0x0E,  // LDA #$0E
+                                 0x85, 0x08,  // STA COLUPF
+                                 0xA9, 0xFF,  // LDA #$FF
+                                 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA, 0xEA,
+                                 0x85, 0x0E]); // STA PF1, after 8 NOPs
+
+    while atari_2600.bus().tia().beam().scanline == 0 {
+        atari_2600.step();
+    }
+
+    // the reset leaves the beam at color clock 21, so the write ends on
+    // clock 21 + 7 * 3 + 8 * 6 + 9 = 99, pixel 31
+    let line = &atari_2600.bus().tia().frame_buffer()[..160];
+
+    assert_eq!(line[30], 0);
+    assert_eq!(line[31], 0x0E);
+    assert_eq!(line[47], 0x0E);
+    assert_eq!(line[48], 0);
+    assert_eq!(line[96], 0x0E);
 }